@@ -0,0 +1,70 @@
+// Times the same breadth-first search twice, once keeping visited boards as sorted container vecs
+// and once as PackedState, to show what packing saves the solver.
+// Usage: cargo run --release --example pack_benchmark [board file]
+// Uses a built-in hard board when no file is given. Prints both timings and the speedup.
+
+use std::collections::{HashSet, VecDeque};
+use std::hash::Hash;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+use water_sort::model::GameState;
+use water_sort::packed::PackedState;
+
+const HARD_BOARD: &str = "ABCD\nEFGH\nIABC\nDEFG\nHIAB\nCDEF\nGHIA\nBCDE\nFGHI\n....\n....";
+
+/// Boards visited and the length of the shortest solution, if there is one.
+fn breadth_first<K: Hash + Eq>(start: &GameState, key: impl Fn(&GameState) -> K) -> (usize, Option<usize>) {
+    let mut visited = HashSet::new();
+    visited.insert(key(start));
+    let mut queue = VecDeque::from([(start.clone(), 0)]);
+    while let Some((state, depth)) = queue.pop_front() {
+        if state.is_solved() {
+            return (visited.len(), Some(depth));
+        }
+        for mv in state.get_possible_moves() {
+            let mut next_state = state.clone();
+            next_state.apply_move(&mv);
+            if visited.insert(key(&next_state)) {
+                queue.push_back((next_state, depth + 1));
+            }
+        }
+    }
+    (visited.len(), None)
+}
+
+fn timed<T>(run: impl FnOnce() -> T) -> (T, Duration) {
+    let start = Instant::now();
+    let result = run();
+    (result, start.elapsed())
+}
+
+fn main() -> ExitCode {
+    let repr = match std::env::args().nth(1) {
+        Some(path) => match std::fs::read_to_string(&path) {
+            Ok(repr) => repr,
+            Err(err) => {
+                eprintln!("Could not read {}: {}", path, err);
+                return ExitCode::FAILURE;
+            }
+        },
+        None => HARD_BOARD.to_string(),
+    };
+    let state = GameState::new_from_repr(&repr);
+
+    let ((unpacked_visited, unpacked_len), unpacked_time) =
+        timed(|| breadth_first(&state, GameState::get_sorted_containers));
+    let ((packed_visited, packed_len), packed_time) = timed(|| breadth_first(&state, PackedState::canonical));
+    if unpacked_visited != packed_visited || unpacked_len != packed_len {
+        eprintln!("The two searches disagree, the packing doesn't match sorted containers.");
+        return ExitCode::FAILURE;
+    }
+    let (_, stats) = state.solve_with_stats();
+
+    println!("{} boards visited, shortest solution {:?} moves.", packed_visited, packed_len);
+    println!("Sorted container vecs: {:.3}s", unpacked_time.as_secs_f64());
+    println!("PackedState:           {:.3}s", packed_time.as_secs_f64());
+    println!("Speedup:               {:.2}x", unpacked_time.as_secs_f64() / packed_time.as_secs_f64());
+    println!("For reference, the solver itself took {:.3}s.", stats.elapsed.as_secs_f64());
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,50 @@
+// Solves a board read from stdin without opening a window, for use from scripts.
+// Usage: cargo run --release --example solve [seconds] < board.txt
+// Prints one move per line, then a line saying whether the solution is optimal.
+// Exits with 1 if the board couldn't be read or solved.
+
+use std::io::Read;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use water_sort::model::GameState;
+use water_sort::solver::BestEffortSolution;
+
+const DEFAULT_BUDGET_SECONDS: f64 = 10.0;
+
+fn main() -> ExitCode {
+    let budget = match std::env::args().nth(1) {
+        Some(arg) => match arg.parse::<f64>() {
+            Ok(seconds) if seconds >= 0.0 => seconds,
+            _ => {
+                eprintln!("Expected a time budget in seconds, got {:?}", arg);
+                return ExitCode::FAILURE;
+            }
+        },
+        None => DEFAULT_BUDGET_SECONDS,
+    };
+    let mut repr = String::new();
+    if let Err(err) = std::io::stdin().read_to_string(&mut repr) {
+        eprintln!("Could not read the board from stdin: {}", err);
+        return ExitCode::FAILURE;
+    }
+    let state = GameState::new_from_repr(&repr);
+    if state.container_count() == 0 {
+        eprintln!("No containers in the input.");
+        return ExitCode::FAILURE;
+    }
+
+    let (moves, verdict) = match state.best_effort_solution(Duration::from_secs_f64(budget)) {
+        BestEffortSolution::Optimal(moves) => (moves, "optimal"),
+        BestEffortSolution::Greedy(moves) => (moves, "timed out, not necessarily optimal"),
+        BestEffortSolution::Unsolved => {
+            println!("unsolvable");
+            return ExitCode::FAILURE;
+        }
+    };
+    for action in &moves {
+        println!("{}", action.to_notation());
+    }
+    println!("{} moves, {}", moves.len(), verdict);
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,24 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use water_sort::model::{FluidContainer, GameState};
+
+// Whatever ends up on the clipboard has to paste without panicking,
+// and once parsed, a board has to come back out of its own text unchanged.
+fuzz_target!(|data: &[u8]| {
+    let text = String::from_utf8_lossy(data);
+    let parsers: [fn(&str) -> GameState; 2] = [GameState::new_from_repr, GameState::new_from_repr_exact];
+    for parse in parsers {
+        let state = parse(&text);
+        let repr = state.get_text_representation();
+        let reparsed = parse(&repr);
+        assert!(reparsed.eq_exact(&state), "{:?} reparsed differently from {:?}", repr, text);
+        assert_eq!(reparsed.get_text_representation(), repr);
+        let versioned = parse(&state.get_versioned_text_representation());
+        assert!(versioned.eq_exact(&state));
+        let compact = parse(&state.get_compact_text_representation());
+        assert!(compact.eq_exact(&state), "{:?} reparsed differently from {:?}", state.get_compact_text_representation(), repr);
+    }
+    let container = FluidContainer::new_from_repr(&text);
+    let _ = container.get_text_representation();
+});
@@ -1,9 +1,25 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::Duration;
 use std::vec;
 
+use crate::generator::{PuzzleParams, DAILY_PARAMS};
 use crate::model::*;
-use crate::renderer::Renderer;
+use crate::renderer::{LayoutInfo, Renderer, Theme};
+use crate::solver::{SolveHandle, SolveStatus, Solver};
 use clipboard_rs::{Clipboard, ClipboardContext};
-use macroquad::prelude::debug;
+use macroquad::prelude::{debug, get_time};
+
+// Time between solution moves at normal animation speed
+const AUTOPLAY_INTERVAL: f64 = 0.4;
+// A to Z
+const SINGLE_LETTER_COLOR_LIMIT: usize = 26;
+// Older checkpoints are dropped past this many, revert_to_last_solvable rarely needs to go far back
+const MAX_CHECKPOINTS: usize = 200;
+// How long each board gets when revert_to_last_solvable checks it, running out stops the search
+const REVERT_CHECK_BUDGET: Duration = Duration::from_secs(3);
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Selection {
@@ -14,6 +30,15 @@ enum Selection {
     Button(usize),
 }
 
+/// A background look for the latest checkpoint the puzzle can still be won from, see `revert_to_last_solvable`.
+struct RevertCheck {
+    handle: SolveHandle,
+    // The board when the look started, any move since calls it off
+    from: GameState,
+    // Index into the checkpoints of the board being solved, or None while it's the current board
+    checkpoint: Option<usize>,
+}
+
 pub struct GameEngine {
     state: GameState,
     starting_state: GameState,
@@ -24,36 +49,121 @@ pub struct GameEngine {
     undo_enable: bool,
     undo_stack: Vec<GameState>,
     redo_stack: Vec<GameState>,
+    // Editor snapshots of single containers, by index. Dropped whenever containers are added, removed or moved.
+    container_history: HashMap<usize, Vec<FluidContainer>>,
     editor_mode: bool,
+    hard_mode: bool,
+    single_letter_colors: bool,
+    show_target: bool,
+    tutorial_mode: bool,
+    move_count: usize,
+    // Boards from before each pour, oldest first and at most MAX_CHECKPOINTS of them
+    checkpoints: VecDeque<GameState>,
+    revert_check: Option<RevertCheck>,
+    // Par for the starting state once it's known, worked out in the background by `par_solve`
+    par: Option<usize>,
+    par_solve: Option<SolveHandle>,
+    // The board the last hint was worked out for, and that hint
+    hint_cache: RefCell<Option<(GameState, Option<MoveAction>)>>,
+    reselect_on_illegal_pour: bool,
+    auto_select_source: bool,
+    new_container_capacity: Option<usize>,
+    pending_solve: Option<SolveHandle>,
+    // A New deal being made in the background, and the starting board it was asked for from
+    pending_scramble: Option<(GameState, Receiver<Option<GameState>>)>,
+    autoplay_queue: VecDeque<MoveAction>,
+    autoplay_paused: bool,
+    played_solution: Vec<MoveAction>,
+    next_autoplay_time: f64,
+    // Set by the Swap button, the container the next click trades places with
+    swap_pending: Option<usize>,
+    // Whether anything drawn has changed since the last frame. Without it the last frame is shown again.
+    needs_redraw: bool,
+    custom_action_handler: Option<CustomActionHandler>,
 }
 
+/// Called with the engine and the id of a `ControlAction::Custom` button when it's clicked.
+pub type CustomActionHandler = Box<dyn FnMut(&mut GameEngine, u32)>;
+
 impl GameEngine {
-    pub fn new(undo_enable: bool) -> Self {
-        let gamestate = GameState {
-            fluid_containers: vec![FluidContainer::new(5), FluidContainer::new(5)],
+    /// Starts either in the editor with four empty containers to build a board in, room for two colors,
+    /// or in play mode with a freshly dealt board.
+    pub fn new(undo_enable: bool, editor_mode: bool) -> Self {
+        let empty_board = GameState {
+            fluid_containers: vec![FluidContainer::new(5); 4],
+            pour_rule: PourRule::default(),
         };
-        let mut swatch_colors: Vec<FluidPacket> = vec![FluidPacket::Empty];
-        for i in 0..10 {
-            swatch_colors.push(FluidPacket::new(i));
-        }
+        let gamestate = if editor_mode {
+            empty_board
+        } else {
+            GameState::generate(DAILY_PARAMS, rand::random()).unwrap_or(empty_board)
+        };
+        let mut engine = Self::new_with_state(gamestate, undo_enable);
+        engine.editor_mode = editor_mode;
+        engine
+    }
+
+    /// The buttons a new engine starts with. Undo and friends are only there with `undo_enable`.
+    /// Start from these and push more to extend the set, see `set_buttons`.
+    pub fn default_buttons(undo_enable: bool) -> Vec<Button> {
         let mut buttons = vec![
             Button::new("Add", ControlAction::AddContainer, FLUID_COLORS[3]), // GREEN
             Button::new("Remove", ControlAction::RemoveContainer, FLUID_COLORS[0]), // RED
             Button::new("Expand", ControlAction::ExpandContainer, FLUID_COLORS[1]), // BLUE
             Button::new("Shrink", ControlAction::ShrinkContainer, FLUID_COLORS[2]), // YELLOW
+            Button::new("Clear", ControlAction::ClearContainer, FLUID_COLORS[14]), // MAROON
+            Button::new("Trim", ControlAction::TrimEmptyContainers(2), FLUID_COLORS[20]), // VIOLET
+            Button::new("Swap", ControlAction::BeginSwap, FLUID_COLORS[15]), // AQUA
             Button::new("Shuffle", ControlAction::ShuffleState, FLUID_COLORS[10]), // BROWN
 
             Button::new("Paste", ControlAction::PasteState, FLUID_COLORS[4]), // PURPLE
             Button::new("Copy", ControlAction::CopyState, FLUID_COLORS[5]), // ORANGE
             Button::new("Editor", ControlAction::ToggleEditor, FLUID_COLORS[6]), // CYAN
+            Button::new("Hint", ControlAction::Hint, FLUID_COLORS[11]), // NAVY
+            Button::new("Solve", ControlAction::SolveFromHere, FLUID_COLORS[12]), // TURQUOISE
+            Button::new("New", ControlAction::NewScramble, FLUID_COLORS[17]), // GOLD
         ];
         if undo_enable {
             buttons.push(Button::new("Undo", ControlAction::Undo, FLUID_COLORS[7])); // MAGENTA
             buttons.push(Button::new("Redo", ControlAction::Redo, FLUID_COLORS[8])); // LIME
+            buttons.push(Button::new("First", ControlAction::UndoAll, FLUID_COLORS[18])); // SILVER
+            buttons.push(Button::new("Last", ControlAction::RedoAll, FLUID_COLORS[19])); // CORAL
+            buttons.push(Button::new("Back", ControlAction::StepBack, FLUID_COLORS[13])); // OLIVE
+            buttons.push(Button::new("Step", ControlAction::StepForward, FLUID_COLORS[16])); // TEAL
         }
         buttons.push(Button::new("Reset", ControlAction::Reset, FLUID_COLORS[9])); // PINK
+        buttons
+    }
+
+    /// Replaces the buttons along the top, e.g. with `default_buttons` plus some `ControlAction::Custom` ones.
+    /// Editor and solver buttons still hide themselves outside the editor and in hard mode.
+    pub fn set_buttons(&mut self, buttons: Vec<Button>) {
+        self.needs_redraw = true;
+        self.buttons = buttons;
+        if matches!(self.selected, Selection::Button(_)) {
+            self.selected = Selection::None;
+        }
+    }
+
+    pub fn get_buttons(&self) -> &[Button] {
+        &self.buttons
+    }
 
-        Self {
+    /// Runs `handler` whenever a `ControlAction::Custom` action comes in, with the id it carries.
+    /// The handler gets the engine itself, so it can read the board or act on it.
+    pub fn set_custom_action_handler(&mut self, handler: impl FnMut(&mut GameEngine, u32) + 'static) {
+        self.custom_action_handler = Some(Box::new(handler));
+    }
+
+    /// Starts straight into playing the given board, which is also what Reset goes back to.
+    pub fn new_with_state(gamestate: GameState, undo_enable: bool) -> Self {
+        let mut swatch_colors: Vec<FluidPacket> = vec![FluidPacket::Empty];
+        for i in 0..10 {
+            swatch_colors.push(FluidPacket::new(i));
+        }
+        let buttons = Self::default_buttons(undo_enable);
+
+        let mut engine = Self {
             state: gamestate.clone(),
             starting_state: gamestate.clone(),
             swatch_colors,
@@ -63,16 +173,234 @@ impl GameEngine {
             undo_enable,
             undo_stack: Vec::new(),
             redo_stack: Vec::new(),
-            editor_mode: true,
-        }
+            container_history: HashMap::new(),
+            editor_mode: false,
+            hard_mode: false,
+            single_letter_colors: false,
+            show_target: false,
+            tutorial_mode: false,
+            move_count: 0,
+            checkpoints: VecDeque::new(),
+            revert_check: None,
+            par: None,
+            par_solve: None,
+            hint_cache: RefCell::new(None),
+            reselect_on_illegal_pour: true,
+            auto_select_source: false,
+            new_container_capacity: None,
+            pending_solve: None,
+            pending_scramble: None,
+            autoplay_queue: VecDeque::new(),
+            autoplay_paused: false,
+            played_solution: Vec::new(),
+            next_autoplay_time: 0.0,
+            swap_pending: None,
+            needs_redraw: true,
+            custom_action_handler: None,
+        };
+        engine.start_par_solve();
+        engine
     }
 
     pub fn is_editor_mode(&self) -> bool {
         self.editor_mode
     }
 
+    pub fn is_hard_mode(&self) -> bool {
+        self.hard_mode
+    }
+
+    /// Hard mode turns off everything that asks the solver for help: hints, solving, and par.
+    pub fn set_hard_mode(&mut self, hard_mode: bool) {
+        self.needs_redraw = true;
+        self.hard_mode = hard_mode;
+        if hard_mode {
+            self.stop_autoplay();
+            self.pending_solve = None;
+        }
+    }
+
+    /// Switches between the default dark colors and a light theme, keeping the empty spot style.
+    pub fn toggle_light_theme(&mut self) {
+        self.needs_redraw = true;
+        let current = self.renderer.get_theme();
+        let theme = if current.background == Theme::light().background {
+            Theme::dark()
+        } else {
+            Theme::light()
+        };
+        self.renderer.set_theme(Theme { empty_packet: current.empty_packet, ..theme });
+    }
+
+    /// Moves on to the next way of drawing empty spots: outlined, faintly filled, invisible, or dotted.
+    pub fn cycle_empty_packet_style(&mut self) {
+        self.needs_redraw = true;
+        let mut theme = self.renderer.get_theme();
+        theme.empty_packet = theme.empty_packet.next();
+        self.renderer.set_theme(theme);
+    }
+
+    pub fn toggle_perf_overlay(&mut self) {
+        self.needs_redraw = true;
+        let show = !self.renderer.is_showing_perf();
+        self.renderer.set_show_perf(show);
+    }
+
+    /// See `Renderer::set_animation_speed`. 0.0 makes pours and solution playback instant.
+    pub fn set_animation_speed(&mut self, speed: f32) {
+        self.renderer.set_animation_speed(speed);
+    }
+
+    /// Switches between instant pours and normal speed ones.
+    pub fn toggle_instant_pours(&mut self) {
+        let speed = if self.renderer.get_animation_speed() == 0.0 { 1.0 } else { 0.0 };
+        self.renderer.set_animation_speed(speed);
+    }
+
+    pub fn toggle_dividers(&mut self) {
+        self.needs_redraw = true;
+        let show = !self.renderer.is_showing_dividers();
+        self.renderer.set_show_dividers(show);
+    }
+
+    /// Where the buttons, board, goal panel and swatches were drawn last frame, for overlaying custom UI.
+    pub fn last_layout(&self) -> LayoutInfo {
+        self.renderer.last_layout()
+    }
+
+    /// Beginner help: while playing, the corner of the board says how many moves are available.
+    /// Watching it drop shows when the board is getting tight.
+    pub fn toggle_tutorial_mode(&mut self) {
+        self.needs_redraw = true;
+        self.tutorial_mode = !self.tutorial_mode;
+    }
+
+    /// Shows or hides a panel with the solved arrangement next to the board.
+    pub fn toggle_target_panel(&mut self) {
+        self.needs_redraw = true;
+        self.show_target = !self.show_target;
+    }
+
+    /// Picks what happens when the selected container can't pour into the clicked one:
+    /// either the clicked container becomes the selection (the default), or the selection stays and the target flashes.
+    pub fn set_reselect_on_illegal_pour(&mut self, reselect: bool) {
+        self.reselect_on_illegal_pour = reselect;
+    }
+
+    /// "Tap the tube you want to fill": while playing with nothing selected, clicking an empty container
+    /// pours into it from the only container that can, if there's just the one.
+    pub fn toggle_auto_select_source(&mut self) {
+        self.auto_select_source = !self.auto_select_source;
+    }
+
+    /// Replaces the palette shown in the swatch row, e.g. with just the colors a board uses.
+    /// A selected swatch stays selected if its packet is still in the new list, wherever it ended up.
+    pub fn set_swatch_colors(&mut self, swatch_colors: Vec<FluidPacket>) {
+        self.needs_redraw = true;
+        if let Selection::Color(index) = self.selected {
+            let selected_packet = self.swatch_colors.get(index).copied();
+            self.selected = match swatch_colors.iter().position(|p| Some(*p) == selected_packet) {
+                Some(new_index) => Selection::Color(new_index),
+                None => Selection::None,
+            };
+        }
+        self.swatch_colors = swatch_colors;
+    }
+
+    pub fn get_swatch_colors(&self) -> &[FluidPacket] {
+        &self.swatch_colors
+    }
+
+    /// Capacity for containers made by Add. None copies the selected (or last) container instead.
+    pub fn set_new_container_capacity(&mut self, capacity: Option<usize>) {
+        self.new_container_capacity = capacity.filter(|&c| c > 0);
+    }
+
+    /// A one-line summary like "12 moves · 3 colors left · solved" for a title bar or status line.
+    pub fn status_string(&self) -> String {
+        let colors_left = self.state.get_available_colors_with_count().len() - self.state.completed_colors().len();
+        let mut parts = vec![
+            format!("{} {}", self.move_count, if self.move_count == 1 { "move" } else { "moves" }),
+            format!("{} {} left", colors_left, if colors_left == 1 { "color" } else { "colors" }),
+        ];
+        if self.state.is_solved() {
+            parts.push("solved".to_string());
+            if let Some(par) = self.par()
+                && !self.hard_mode
+            {
+                parts.push(format!("par {}", par));
+            }
+        }
+        if self.editor_mode {
+            parts.push("editing".to_string());
+        }
+        parts.join(" · ")
+    }
+
+    /// Every pour made since the board was loaded or reset. Undoing a pour doesn't take it back.
+    pub fn get_move_count(&self) -> usize {
+        self.move_count
+    }
+
+    /// The optimal move count for the starting state, or None if it can't be solved or hasn't been worked out yet.
+    /// Solving is expensive, so it runs in the background from when the board is loaded and `update` picks it up.
+    pub fn par(&self) -> Option<usize> {
+        self.par
+    }
+
+    fn start_par_solve(&mut self) {
+        self.par = None;
+        self.par_solve = Some(Solver::spawn(self.starting_state.clone()));
+    }
+
+    fn poll_par_solve(&mut self) {
+        let Some(handle) = &self.par_solve else {
+            return;
+        };
+        match handle.poll() {
+            SolveStatus::Running => return,
+            SolveStatus::Solved(solution) => self.par = Some(solution.len()),
+            SolveStatus::Unsolvable | SolveStatus::TimedOut | SolveStatus::Cancelled => {}
+        }
+        self.par_solve = None;
+    }
+
+    /// The move the Hint button would suggest, without selecting anything, or None if there's nothing to suggest.
+    /// Always None in hard mode. The answer is kept until the board changes.
+    pub fn peek_hint(&self) -> Option<MoveAction> {
+        if self.hard_mode {
+            return None;
+        }
+        let mut cache = self.hint_cache.borrow_mut();
+        if let Some((state, hint)) = cache.as_ref()
+            && state.eq_exact(&self.state)
+        {
+            return *hint;
+        }
+        let hint = self.state.hint();
+        *cache = Some((self.state.clone(), hint));
+        hint
+    }
+
+    /// Makes the next `render` draw the board from scratch, for changes made behind the engine's back.
+    pub fn request_redraw(&mut self) {
+        self.needs_redraw = true;
+    }
+
+    /// Whether the next `render` has to draw the board rather than show the last frame again.
+    pub fn needs_redraw(&self) -> bool {
+        self.needs_redraw || self.renderer.has_moving_parts()
+    }
+
+    /// Draws the board, or when nothing has changed since the last call just shows that frame again,
+    /// so an idle board costs one textured quad a frame.
     pub fn render(&mut self) {
-        self.renderer.autoset_viewport();
+        let resized = self.renderer.autoset_viewport();
+        let moving = self.renderer.take_moving_parts();
+        if !self.needs_redraw && !resized && !moving && self.renderer.draw_cached_frame() {
+            return;
+        }
+        self.needs_redraw = false;
         let (selected_container, selected_swatch, selected_button) = match &self.selected {
             Selection::Container(index) => (Some(*index), None, None),
             Selection::Color(index) => (None, Some(*index), None),
@@ -80,12 +408,34 @@ impl GameEngine {
             Selection::None => (None, None, None),
         };
         let containers = &self.state.fluid_containers.iter().collect::<Vec<_>>();
-        let buttons = &self.buttons.iter().filter(|b| !b.editor_mode() || self.editor_mode).collect::<Vec<_>>();
-        let swatches = if self.editor_mode {
+        let buttons = &self.buttons
+            .iter()
+            .filter(|b| !b.editor_mode() || self.editor_mode)
+            .filter(|b| !b.solver_assisted() || !self.hard_mode)
+            .collect::<Vec<_>>();
+        // During play a swatch picks a color for the solver to move along, which hard mode doesn't allow
+        let swatches = if self.editor_mode || !self.hard_mode {
             self.swatch_colors.as_slice()
         } else {
             &[]
         };
+        let target = if self.show_target {
+            self.state.solved_target()
+        } else {
+            vec![]
+        };
+        self.renderer.set_target(target);
+        let status = if self.tutorial_mode && !self.editor_mode {
+            Some(match self.state.legal_move_count() {
+                1 => "1 move available".to_string(),
+                count => format!("{} moves available", count),
+            })
+        } else {
+            None
+        };
+        self.renderer.set_status_text(status);
+        self.renderer.set_completed_colors(self.state.completed_colors());
+        self.renderer.begin_cached_frame();
         self.renderer.render_game(
             containers,
             swatches,
@@ -94,6 +444,135 @@ impl GameEngine {
             selected_swatch,
             selected_button,
         );
+        self.renderer.end_cached_frame();
+    }
+
+    /// Advances anything that plays out over time. Call once per frame.
+    pub fn update(&mut self) {
+        self.poll_pending_solve();
+        self.poll_pending_scramble();
+        self.poll_par_solve();
+        self.poll_revert_check();
+        if self.autoplay_paused || self.autoplay_queue.is_empty() || get_time() < self.next_autoplay_time {
+            return;
+        }
+        self.next_autoplay_time = get_time() + self.renderer.scale_duration(AUTOPLAY_INTERVAL);
+        self.play_next_solution_move();
+    }
+
+    /// Plays the next move of the queued solution and pauses autoplay so the rest can be studied move by move.
+    pub fn step_solution_forward(&mut self) {
+        self.needs_redraw = true;
+        self.autoplay_paused = true;
+        self.play_next_solution_move();
+    }
+
+    /// Takes back the last solution move played through the undo stack and pauses autoplay.
+    pub fn step_solution_back(&mut self) {
+        self.needs_redraw = true;
+        self.autoplay_paused = true;
+        if let Some(last_move) = self.played_solution.pop() {
+            let before = self.get_state();
+            self.undo();
+            self.autoplay_queue.push_front(last_move);
+            self.highlight_changes_since(&before);
+        }
+    }
+
+    fn play_next_solution_move(&mut self) {
+        self.needs_redraw = true;
+        let Some(next_move) = self.autoplay_queue.pop_front() else {
+            return;
+        };
+        if !self.state.could_apply_move(next_move.from_container, next_move.to_container) {
+            debug!("Solution move {} no longer applies, stopping.", next_move.to_notation());
+            self.stop_autoplay();
+            return;
+        }
+        let before = self.get_state();
+        self.handle_game_action(ControlAction::PourInto(next_move.from_container, next_move.to_container));
+        self.played_solution.push(next_move);
+        self.highlight_changes_since(&before);
+    }
+
+    /// Outlines the containers a solution move just touched, so it's clear what moved.
+    fn highlight_changes_since(&mut self, before: &GameState) {
+        let changed = before.diff(&self.state).iter().map(|diff| diff.index).collect();
+        self.renderer.set_highlighted_containers(changed);
+    }
+
+    /// Moves the container selection to the next (or previous) container, wrapping around.
+    /// With `skip_empty`, empty containers are passed over unless they're all empty.
+    pub fn cycle_container_selection(&mut self, forward: bool, skip_empty: bool) {
+        self.needs_redraw = true;
+        let count = self.state.container_count();
+        if count == 0 {
+            return;
+        }
+        let mut index = match self.selected {
+            Selection::Container(index) if index < count => index,
+            _ if forward => count - 1,
+            _ => 0,
+        };
+        for _ in 0..count {
+            index = if forward { (index + 1) % count } else { (index + count - 1) % count };
+            let is_empty = self.state.container(index).is_some_and(|c| c.is_empty());
+            if !skip_empty || !is_empty {
+                self.handle_game_action(ControlAction::SelectContainer(index));
+                return;
+            }
+        }
+    }
+
+    fn poll_pending_scramble(&mut self) {
+        let Some((scrambled_from, receiver)) = &self.pending_scramble else {
+            return;
+        };
+        let new_state = match receiver.try_recv() {
+            Err(TryRecvError::Empty) => return,
+            Ok(new_state) => new_state,
+            Err(TryRecvError::Disconnected) => None,
+        };
+        let still_wanted = scrambled_from.eq_exact(&self.starting_state);
+        self.pending_scramble = None;
+        match new_state {
+            Some(_) if !still_wanted => debug!("A different board was loaded while dealing, dropping the new deal."),
+            Some(mut new_state) => {
+                self.push_undo_state();
+                new_state.pour_rule = self.state.pour_rule;
+                self.start_new_puzzle(new_state);
+            }
+            None => debug!("Could not scramble a new board like this one."),
+        }
+    }
+
+    fn poll_pending_solve(&mut self) {
+        let Some(handle) = &self.pending_solve else {
+            return;
+        };
+        match handle.poll() {
+            SolveStatus::Running => return,
+            SolveStatus::Solved(solution) if handle.is_for(&self.state) => {
+                debug!("Autoplaying a {} move solution.", solution.len());
+                self.selected = Selection::None;
+                self.autoplay_queue = solution.into();
+                self.next_autoplay_time = get_time() + self.renderer.scale_duration(AUTOPLAY_INTERVAL);
+            }
+            SolveStatus::Solved(_) => debug!("The board changed while solving, dropping the solution."),
+            SolveStatus::Unsolvable => debug!("The current state can't be solved."),
+            SolveStatus::TimedOut | SolveStatus::Cancelled => {}
+        }
+        self.pending_solve = None;
+        self.needs_redraw = true;
+    }
+
+    fn stop_autoplay(&mut self) {
+        self.needs_redraw = true;
+        self.pending_solve = None;
+        self.autoplay_queue.clear();
+        self.played_solution.clear();
+        self.autoplay_paused = false;
+        self.renderer.set_highlighted_containers(vec![]);
     }
 
     pub fn handle_click(&mut self, x: f32, y: f32, is_right_click: bool) {
@@ -102,17 +581,96 @@ impl GameEngine {
         }
     }
 
+    /// Call with the mouse position every frame. Hovering a color swatch outlines every packet of that color on the board.
+    pub fn handle_hover(&mut self, x: f32, y: f32) {
+        let hovered_color = match self.renderer.get_hit_test_registry().hit_test(x, y).map(|hit| hit.item) {
+            Some(HitItem::Swatch { index }) => self.swatch_colors.get(index).and_then(|packet| packet.get_color_id()),
+            _ => None,
+        };
+        let positions = hovered_color.map_or(vec![], |color_id| self.state.color_positions(color_id));
+        if self.renderer.get_highlighted_packets() != positions.as_slice() {
+            self.renderer.set_highlighted_packets(positions);
+            self.needs_redraw = true;
+        }
+    }
+
+    /// The second click of a double click, in place of a normal click.
+    /// On the container just poured into, it drops the selection so the pour is done with.
+    /// On a container the first click selected, it pours into the only place it can go, if there's exactly one.
+    /// Anything else is handled as a normal click.
+    pub fn handle_double_click(&mut self, x: f32, y: f32) {
+        let Some(index) = self
+            .renderer
+            .get_hit_test_registry()
+            .hit_test(x, y)
+            .and_then(|hit| hit.item.container_index())
+        else {
+            self.handle_click(x, y, false);
+            return;
+        };
+        match self.selected {
+            Selection::Container(from) if from != index => {
+                self.handle_game_action(ControlAction::Deselect);
+            }
+            Selection::Container(_) => {
+                let destinations: Vec<usize> = (0..self.state.container_count())
+                    .filter(|&to| self.state.could_apply_move(index, to))
+                    .collect();
+                if let [to] = destinations[..] {
+                    self.stop_autoplay();
+                    self.handle_game_action(ControlAction::PourInto(index, to));
+                    self.selected = Selection::None;
+                } else {
+                    self.handle_click(x, y, false);
+                }
+            }
+            _ => self.handle_click(x, y, false),
+        }
+    }
+
+    /// A left-button drag from one container onto another moves it there, in the editor.
+    pub fn handle_drag(&mut self, start: (f32, f32), end: (f32, f32)) {
+        let registry = self.renderer.get_hit_test_registry();
+        let from = registry.hit_test(start.0, start.1).and_then(|hit| hit.item.container_index());
+        let to = registry.hit_test(end.0, end.1).and_then(|hit| hit.item.container_index());
+        if let (Some(from), Some(to)) = (from, to)
+            && from != to
+        {
+            self.stop_autoplay();
+            self.handle_game_action(ControlAction::MoveContainer(from, to));
+        }
+    }
+
     fn handle_hit_item(&mut self, item: HitItem, is_right_click: bool) {
+        // A pending swap only lasts one click, whatever it lands on
+        if let Some(from) = self.swap_pending.take()
+            && let Some(to) = item.container_index()
+        {
+            let action = if from == to { ControlAction::Deselect } else { ControlAction::SwapContainers(from, to) };
+            self.stop_autoplay();
+            self.handle_game_action(action);
+            return;
+        }
         let action = match &item {
             HitItem::Button { function } => {
                 *function
             }
             HitItem::Container { index } => {
                 match &self.selected {
+                    Selection::Color(color_index) if !self.is_editor_mode() => {
+                        // Outside the editor a selected color asks the solver to move it along.
+                        // Hard mode hides the swatches, but a color picked before turning it on can still be selected.
+                        match self.swatch_colors[*color_index] {
+                            FluidPacket::Fluid { color_id, .. } if !self.hard_mode => ControlAction::AdvanceColor(color_id),
+                            _ => ControlAction::SelectContainer(*index),
+                        }
+                    }
                     Selection::Color(color_index) => {
+                        // Right click fills the whole container instead of adding one packet
                         match self.swatch_colors[*color_index] {
-                            FluidPacket::Empty => ControlAction::RemoveColor(*index),
-                            FluidPacket::Fluid { color_id } => ControlAction::AddColor(*index, color_id)
+                            FluidPacket::Fluid { color_id, .. } if is_right_click => ControlAction::FillContainer(*index, color_id),
+                            FluidPacket::Fluid { color_id, .. } => ControlAction::AddColor(*index, color_id),
+                            _ => ControlAction::RemoveColor(*index),
                         }
                     }
                     Selection::Container(from_index) => {
@@ -125,7 +683,18 @@ impl GameEngine {
                         }
                     }
                     Selection::Button(_) | Selection::None => {
-                        ControlAction::SelectContainer(*index)
+                        let source = if self.auto_select_source
+                            && !self.is_editor_mode()
+                            && self.state.container(*index).is_some_and(|c| c.is_empty())
+                        {
+                            self.state.unique_source_for(*index)
+                        } else {
+                            None
+                        };
+                        match source {
+                            Some(from) => ControlAction::PourInto(from, *index),
+                            None => ControlAction::SelectContainer(*index),
+                        }
                     }
                 }
             }
@@ -133,6 +702,13 @@ impl GameEngine {
                 self.handle_hit_item(HitItem::Container { index: *index }, is_right_click);
                 return;
             }
+            HitItem::Swatch { index } if is_right_click && !matches!(self.selected, Selection::Container(_)) => {
+                // Right clicking a color takes it off the whole board, unless it's filling the selected container
+                match self.swatch_colors[*index] {
+                    FluidPacket::Fluid { color_id, .. } => ControlAction::PurgeColor(color_id),
+                    _ => return,
+                }
+            }
             HitItem::Swatch { index } => {
                 match &self.selected {
                     Selection::Color(selected_index) => {
@@ -144,8 +720,9 @@ impl GameEngine {
                     }
                     Selection::Container(selected_index) => {
                         match self.swatch_colors[*index] {
-                            FluidPacket::Empty => ControlAction::RemoveColor(*selected_index),
-                            FluidPacket::Fluid { color_id } => ControlAction::AddColor(*selected_index, color_id)
+                            FluidPacket::Fluid { color_id, .. } if is_right_click => ControlAction::FillContainer(*selected_index, color_id),
+                            FluidPacket::Fluid { color_id, .. } => ControlAction::AddColor(*selected_index, color_id),
+                            _ => ControlAction::RemoveColor(*selected_index),
                         }
                     }
                     Selection::Button(_) | Selection::None => {
@@ -154,23 +731,44 @@ impl GameEngine {
                 }
             }
         };
+        // Anything other than stepping takes control back from a queued solution
+        if !matches!(action, ControlAction::StepForward | ControlAction::StepBack) {
+            self.stop_autoplay();
+        }
         self.handle_game_action(action);
     }
 
     pub fn handle_game_action(&mut self, action: ControlAction) {
+        self.needs_redraw = true;
         if matches!(action, 
             ControlAction::PasteState|
             ControlAction::AddColor(_,_)|
+            ControlAction::FillContainer(_,_)|
             ControlAction::RemoveColor(_)|
             ControlAction::AddContainer|
             ControlAction::RemoveContainer|
             ControlAction::ExpandContainer|
             ControlAction::ShrinkContainer|
+            ControlAction::ClearContainer|
+            ControlAction::TrimEmptyContainers(_)|
+            ControlAction::PurgeColor(_)|
+            ControlAction::MoveContainer(_, _)|
+            ControlAction::BeginSwap|
+            ControlAction::SwapContainers(_, _)|
             ControlAction::ReversePour(_, _, _)|
             ControlAction::ShuffleState
         ) && !self.is_editor_mode() {
             return;
         }
+        if matches!(action,
+            ControlAction::Hint|
+            ControlAction::AdvanceColor(_)|
+            ControlAction::SolveFromHere|
+            ControlAction::StepForward|
+            ControlAction::StepBack
+        ) && self.is_hard_mode() {
+            return;
+        }
         match action {
             ControlAction::SelectColor(index) => {
                 self.selected = Selection::Color(index);
@@ -182,21 +780,34 @@ impl GameEngine {
                 self.selected = Selection::None;
             }
             ControlAction::PourInto(from, to) => {
-                if !self.state.fluid_containers[from].could_pour_into(&self.state.fluid_containers[to]) {
-                    self.handle_game_action(ControlAction::SelectContainer(to));
+                if !self.state.could_apply_move(from, to) {
+                    self.reject_pour(to);
                     return;
                 }
                 self.push_undo_state();
+                self.checkpoints.push_back(self.get_state());
+                if self.checkpoints.len() > MAX_CHECKPOINTS {
+                    self.checkpoints.pop_front();
+                }
                 let current_entropy = self.state.get_entropy();
+                let filled_before = self.state.fluid_containers[to].get_filled_amount();
                 self.state.apply_move(&MoveAction {
                     from_container: from,
                     to_container: to,
                     amount: 0,
                 });
+                let poured = self.state.fluid_containers[to].get_filled_amount() - filled_before;
+                self.renderer.animate_pour(&self.state.fluid_containers[from], &self.state.fluid_containers[to], from, to, poured);
+                self.renderer.show_move_arrow(from, to);
                 let new_entropy = self.state.get_entropy();
                 if new_entropy >= current_entropy {
                     debug!("Congrats you found a move that doesn't decrease entropy!");
                 }
+                self.move_count += 1;
+                // The status line says so too, along with par once it's known
+                if self.state.is_solved() {
+                    debug!("Solved in {} moves.", self.move_count);
+                }
             }
             ControlAction::Undo => {
                 self.undo();
@@ -204,35 +815,120 @@ impl GameEngine {
             ControlAction::Redo => {
                 self.redo();
             }
+            ControlAction::UndoAll => {
+                while self.undo() {}
+            }
+            ControlAction::RedoAll => {
+                while self.redo() {}
+            }
             ControlAction::Reset => {
                 self.push_undo_state();
                 self.load_state(self.starting_state.clone());
+                self.move_count = 0;
+                self.checkpoints.clear();
+            }
+            ControlAction::Custom(id) => {
+                // Taken out while it runs, since it's handed the whole engine
+                match self.custom_action_handler.take() {
+                    Some(mut handler) => {
+                        handler(self, id);
+                        // Unless the handler installed a replacement for itself
+                        if self.custom_action_handler.is_none() {
+                            self.custom_action_handler = Some(handler);
+                        }
+                    }
+                    None => debug!("No handler for custom action {}.", id),
+                }
+            }
+            ControlAction::NewScramble => {
+                // A different deal of the same size, unlike Reset which replays the exact same one.
+                // Dealing runs the solver on every attempt, so it happens off the frame loop and `update` picks it up.
+                let params = PuzzleParams::from_state(&self.starting_state);
+                let seed = rand::random();
+                let (sender, receiver) = mpsc::channel();
+                thread::spawn(move || {
+                    // The engine may have stopped waiting, then there's nobody to tell
+                    let _ = sender.send(GameState::generate(params, seed));
+                });
+                self.pending_scramble = Some((self.starting_state.clone(), receiver));
             }
             ControlAction::ToggleEditor => {
                 self.editor_mode = !self.is_editor_mode();
             }
             ControlAction::CopyState => {
-                let repr = self.state.get_text_representation();
+                let repr = self.state.get_versioned_text_representation();
                 self.set_clipboard(&repr);
             }
+            ControlAction::Hint => {
+                // Select the source of the next move so the player only has to pick the target
+                match self.peek_hint() {
+                    Some(hint) => self.selected = Selection::Container(hint.from_container),
+                    None => debug!("No hint available for the current state."),
+                }
+            }
+            ControlAction::SolveFromHere => {
+                // Solving can take a while, so it runs in the background and update() starts autoplay when it's done
+                self.stop_autoplay();
+                self.pending_solve = Some(Solver::spawn(self.get_state()));
+            }
+            ControlAction::StepForward => {
+                self.step_solution_forward();
+            }
+            ControlAction::StepBack => {
+                self.step_solution_back();
+            }
+            ControlAction::AdvanceColor(color_id) => {
+                match self.state.hint_for_color(color_id) {
+                    Some(hint) => {
+                        self.handle_game_action(ControlAction::PourInto(hint.from_container, hint.to_container));
+                        return;
+                    }
+                    None => debug!("No helpful move found for color {}.", color_id),
+                }
+            }
             // Everything past this point requires editor mode 
             ControlAction::PasteState => {
+                let Some(repr) = self.get_clipboard() else {
+                    return;
+                };
+                let mut new_state = GameState::new_from_repr(&repr);
+                if self.single_letter_colors && new_state.get_available_colors().iter().any(|&id| id >= SINGLE_LETTER_COLOR_LIMIT) {
+                    debug!("The pasted board has colors past Z, which single letter mode doesn't allow.");
+                    return;
+                }
                 if !self.undo_stack.is_empty() {
                     self.push_undo_state();
                 }
-                let repr = self.get_clipboard();
-                let new_state = GameState::new_from_repr(&repr);
-                self.load_state(new_state);
-                self.starting_state = self.state.clone();
+                new_state.pour_rule = self.state.pour_rule;
+                self.start_new_puzzle(new_state);
             }
             ControlAction::AddColor(container_id, color_id) => {
+                if !self.has_room_for_color(color_id) {
+                    return;
+                }
                 self.push_undo_state();
+                self.remember_container(container_id);
                 let packet = FluidPacket::new(color_id);
-                self.state.fluid_containers[container_id].add_fluid(packet);
+                if let Some(container) = self.state.container_mut(container_id) {
+                    container.add_fluid(packet);
+                }
+            }
+            ControlAction::FillContainer(container_id, color_id) => {
+                if self.state.container(container_id).is_none_or(|c| c.is_full()) || !self.has_room_for_color(color_id) {
+                    return;
+                }
+                self.push_undo_state();
+                self.remember_container(container_id);
+                if let Some(container) = self.state.container_mut(container_id) {
+                    container.fill_with(FluidPacket::new(color_id));
+                }
             }
             ControlAction::RemoveColor(container_id) => {
                 self.push_undo_state();
-                self.state.fluid_containers[container_id].pop_fluid();
+                self.remember_container(container_id);
+                if let Some(container) = self.state.container_mut(container_id) {
+                    container.pop_fluid();
+                }
             }
             ControlAction::AddContainer => {
                 self.push_undo_state();
@@ -245,27 +941,80 @@ impl GameEngine {
             ControlAction::ExpandContainer => {
                 self.push_undo_state();
                 if let Selection::Container(index) = self.selected {
-                    self.state.fluid_containers[index].change_capacity(1);
+                    self.remember_container(index);
+                    if let Some(container) = self.state.container_mut(index) {
+                        container.change_capacity(1);
+                    }
                 }
             }
             ControlAction::ShrinkContainer => {
                 self.push_undo_state();
                 if let Selection::Container(index) = self.selected {
-                    self.state.fluid_containers[index].change_capacity(-1);
+                    self.remember_container(index);
+                    if let Some(container) = self.state.container_mut(index) {
+                        container.change_capacity(-1);
+                    }
                 }
             }
-            ControlAction::ReversePour(from, to, amount) => {
-                if !self.state.fluid_containers[from].could_reverse_pour_into(&self.state.fluid_containers[to]) {
-                    self.handle_game_action(ControlAction::SelectContainer(to));
+            ControlAction::ClearContainer => {
+                if let Selection::Container(index) = self.selected
+                    && self.state.container(index).is_some_and(|c| !c.is_empty())
+                {
+                    self.push_undo_state();
+                    self.remember_container(index);
+                    if let Some(container) = self.state.container_mut(index) {
+                        container.clear();
+                    }
+                }
+            }
+            ControlAction::PurgeColor(color_id) => {
+                if !self.state.get_available_colors().contains(&color_id) {
                     return;
                 }
                 self.push_undo_state();
-                let current_entropy = self.state.get_entropy();
-                self.state.apply_reverse_move(&MoveAction {
+                for container in self.state.fluid_containers.iter_mut() {
+                    container.remove_color(color_id);
+                }
+                // Whatever was under the purged color may be on top now
+                self.state.reveal_tops();
+            }
+            ControlAction::MoveContainer(from, to) => {
+                self.move_container(from, to);
+            }
+            ControlAction::BeginSwap => match self.selected {
+                Selection::Container(index) => self.swap_pending = Some(index),
+                _ => debug!("Select a container before pressing swap."),
+            },
+            ControlAction::SwapContainers(a, b) => {
+                if a == b || a >= self.state.container_count() || b >= self.state.container_count() {
+                    return;
+                }
+                self.push_undo_state();
+                self.container_history.clear();
+                self.state.swap_containers(a, b);
+                self.selected = Selection::Container(b);
+            }
+            ControlAction::TrimEmptyContainers(keep) => {
+                self.trim_empty_containers(keep);
+            }
+            ControlAction::ReversePour(from, to, amount) => {
+                // Tried on a copy first, so a reverse pour that moves nothing leaves no undo step behind
+                let mut next_state = self.state.clone();
+                let moved = next_state.apply_reverse_move(&MoveAction {
                     from_container: from,
                     to_container: to,
                     amount,
                 });
+                if moved == 0 {
+                    self.reject_pour(to);
+                    return;
+                }
+                if moved < amount {
+                    debug!("Only {} of the {} packets asked for could be poured back.", moved, amount);
+                }
+                self.push_undo_state();
+                let current_entropy = self.state.get_entropy();
+                self.state = next_state;
                 let new_entropy = self.state.get_entropy();
                 if new_entropy <= current_entropy {
                     debug!("Congrats you found a move that doesn't increase entropy!");
@@ -276,69 +1025,256 @@ impl GameEngine {
                 self.state.shuffle();
             }
         }
+        self.clamp_selection();
         self.render();
     }
 
+    /// Caps the board at the 26 colors that have a single letter, A to Z. Adding a color past Z and
+    /// pasting a board that has one are refused, so copied boards never need two-letter colors or the comma
+    /// separated lines that come with them, and simple parsers can read them one character at a time.
+    /// Off by default: boards can then have any number of colors, at the cost of that simpler format.
+    pub fn set_single_letter_colors(&mut self, single_letter_colors: bool) {
+        self.single_letter_colors = single_letter_colors;
+    }
+
+    pub fn set_pour_rule(&mut self, rule: PourRule) {
+        self.needs_redraw = true;
+        self.state.pour_rule = rule;
+        self.starting_state.pour_rule = rule;
+        self.start_par_solve();
+    }
+
+    /// Starts looking for the latest state the puzzle could still be won from, and jumps back to it once found.
+    /// Every pour leaves a checkpoint, but they're only run through the solver here, newest first and in the
+    /// background, so playing stays cheap and the game keeps drawing. `update` carries the search along.
+    /// Making a move while it looks calls it off. Returns false if it couldn't start looking.
+    pub fn revert_to_last_solvable(&mut self) -> bool {
+        self.needs_redraw = true;
+        if self.hard_mode || self.revert_check.is_some() {
+            return false;
+        }
+        // The solver won't judge a board with hidden packets, so neither it nor earlier checkpoints can be ruled out
+        if !self.state.is_fully_revealed() {
+            debug!("Can't tell whether the current state is solvable while packets are hidden.");
+            return false;
+        }
+        self.revert_check = Some(RevertCheck {
+            handle: Solver::spawn_with_budget(self.get_state(), REVERT_CHECK_BUDGET),
+            from: self.get_state(),
+            checkpoint: None,
+        });
+        true
+    }
+
+    fn poll_revert_check(&mut self) {
+        let Some(check) = &self.revert_check else {
+            return;
+        };
+        let status = check.handle.poll();
+        if status == SolveStatus::Running {
+            return;
+        }
+        let Some(check) = self.revert_check.take() else {
+            return;
+        };
+        if !check.from.eq_exact(&self.state) {
+            debug!("The board changed while looking for a solvable checkpoint.");
+            return;
+        }
+        match (status, check.checkpoint) {
+            (SolveStatus::Solved(_), None) => debug!("The current state is still solvable."),
+            (SolveStatus::Solved(_), Some(index)) => {
+                // Everything after it has been checked and can't be won
+                self.checkpoints.truncate(index + 1);
+                let checkpoint = self.checkpoints[index].clone();
+                self.push_undo_state();
+                self.load_state(checkpoint);
+            }
+            (SolveStatus::Unsolvable, checked) => {
+                let checked = checked.unwrap_or(self.checkpoints.len());
+                // Packets only ever turn face up, so once one checkpoint still had hidden packets every earlier one did too
+                let next = checked.checked_sub(1).filter(|&index| self.checkpoints[index].is_fully_revealed());
+                match next {
+                    Some(index) => {
+                        self.revert_check = Some(RevertCheck {
+                            handle: Solver::spawn_with_budget(self.checkpoints[index].clone(), REVERT_CHECK_BUDGET),
+                            from: check.from,
+                            checkpoint: Some(index),
+                        });
+                    }
+                    None => {
+                        debug!("No solvable checkpoint to go back to.");
+                        self.checkpoints.truncate(checked);
+                    }
+                }
+            }
+            // Checkpoints that couldn't be judged in time are kept
+            (SolveStatus::TimedOut, _) => debug!("Gave up looking for a solvable checkpoint, the solver ran out of time."),
+            (SolveStatus::Running | SolveStatus::Cancelled, _) => {}
+        }
+    }
+
+    fn reject_pour(&mut self, to: usize) {
+        if self.reselect_on_illegal_pour {
+            self.handle_game_action(ControlAction::SelectContainer(to));
+        } else {
+            self.renderer.flash_container(to);
+            self.render();
+        }
+    }
+
+    /// Copies the board in the run-length format, which is shorter for big mostly empty containers.
+    pub fn copy_compact_state(&self) {
+        let repr = format!("version={}\n{}", FORMAT_VERSION, self.state.get_compact_text_representation());
+        self.set_clipboard(&repr);
+    }
+
     pub fn get_state(&self) -> GameState {
         self.state.clone()
     }
 
     pub fn load_state(&mut self, state: GameState) {
+        self.needs_redraw = true;
         self.state = state;
+        self.container_history.clear();
         self.selected = Selection::None;
     }
 
+    /// Loads a board and makes it what Reset goes back to.
+    fn start_new_puzzle(&mut self, state: GameState) {
+        self.load_state(state);
+        self.starting_state = self.state.clone();
+        self.move_count = 0;
+        self.checkpoints.clear();
+        self.start_par_solve();
+    }
+
     fn push_undo_state(&mut self) {
         if self.undo_enable {
+            // Compare exactly, a reordered board is still a change worth undoing.
+            if self.undo_stack.last().is_some_and(|last| last.eq_exact(&self.state)) {
+                return;
+            }
             let snapshot = self.get_state();
             self.undo_stack.push(snapshot);
             self.redo_stack.clear();
         }
     }
 
-    fn undo (&mut self) {
+    fn undo (&mut self) -> bool {
         if self.undo_enable && let Some(previous_state) = self.undo_stack.pop() {
             self.redo_stack.push(self.get_state());
             self.state = previous_state;
+            // Undoing a pour lands back on its checkpoint, which is then the current board rather than a step back
+            if self.checkpoints.back().is_some_and(|checkpoint| checkpoint.eq_exact(&self.state)) {
+                self.checkpoints.pop_back();
+            }
+            self.container_history.clear();
             self.selected = Selection::None;
+            return true;
         }
+        false
     }
 
-    fn redo(&mut self) {
+    fn redo(&mut self) -> bool {
         if self.undo_enable && let Some(next_state) = self.redo_stack.pop() {
             self.undo_stack.push(self.get_state());
             self.state = next_state;
+            self.container_history.clear();
             self.selected = Selection::None;
+            return true;
         }
+        false
     }
 
-    fn get_clipboard(&self) -> String {
-        let ctx = ClipboardContext::new().unwrap();
-        ctx.get_text().unwrap_or("".to_string())
+    /// None if there's no clipboard to read from, so a missing backend doesn't take the game down with it.
+    fn get_clipboard(&self) -> Option<String> {
+        match ClipboardContext::new() {
+            Ok(ctx) => Some(ctx.get_text().unwrap_or_default()),
+            Err(err) => {
+                debug!("Clipboard unavailable: {}", err);
+                None
+            }
+        }
     }
 
     fn set_clipboard(&self, content: &str) {
-        let ctx = ClipboardContext::new().unwrap();
-        let _ = ctx.set_text(content.to_string());
+        match ClipboardContext::new() {
+            Ok(ctx) => {
+                if let Err(err) = ctx.set_text(content.to_string()) {
+                    debug!("Could not copy to the clipboard: {}", err);
+                }
+            }
+            Err(err) => debug!("Clipboard unavailable: {}", err),
+        }
     }
 
     fn add_container(&mut self) {
+        self.container_history.clear();
         match self.selected {
-            Selection::Container(index) => {
-                let capacity = self.state.fluid_containers[index].get_capacity();
+            Selection::Container(index) if index < self.state.container_count() => {
+                let capacity = self.new_container_capacity
+                    .unwrap_or_else(|| self.state.container(index).map_or(5, |c| c.get_capacity()));
                 self.state.fluid_containers.insert(index + 1, FluidContainer::new(capacity));
                 self.selected = Selection::Container(index + 1);
             }
             _ => {
-                let capacity = self.state.fluid_containers.last().map_or(5, |c| c.get_capacity());
+                let capacity = self.new_container_capacity
+                    .unwrap_or_else(|| self.state.fluid_containers.last().map_or(5, |c| c.get_capacity()));
                 self.state.fluid_containers.push(FluidContainer::new(capacity));
-                self.selected = Selection::Container(self.state.fluid_containers.len() - 1);
+                self.selected = Selection::Container(self.state.container_count() - 1);
+            }
+        }
+    }
+    /// Takes a container out and puts it back in at `to_index`, shifting the ones in between.
+    /// Order doesn't matter to the solver, only to how the board looks and copies.
+    pub fn move_container(&mut self, from_index: usize, to_index: usize) -> bool {
+        self.needs_redraw = true;
+        let count = self.state.container_count();
+        if from_index >= count || to_index >= count || from_index == to_index {
+            return false;
+        }
+        self.push_undo_state();
+        self.container_history.clear();
+        let container = self.state.fluid_containers.remove(from_index);
+        self.state.fluid_containers.insert(to_index, container);
+        self.selected = Selection::Container(to_index);
+        true
+    }
+
+    /// Removes empty containers until at most `keep` of each capacity are left, dropping the later ones first.
+    /// Returns how many were removed.
+    pub fn trim_empty_containers(&mut self, keep: usize) -> usize {
+        self.needs_redraw = true;
+        let mut kept_per_capacity: HashMap<usize, usize> = HashMap::new();
+        let mut redundant = vec![];
+        for (index, container) in self.state.fluid_containers.iter().enumerate() {
+            if !container.is_empty() {
+                continue;
+            }
+            let kept = kept_per_capacity.entry(container.get_capacity()).or_insert(0);
+            if *kept < keep {
+                *kept += 1;
+            } else {
+                redundant.push(index);
             }
         }
+        if redundant.is_empty() {
+            return 0;
+        }
+        self.push_undo_state();
+        self.container_history.clear();
+        for &index in redundant.iter().rev() {
+            self.state.fluid_containers.remove(index);
+        }
+        self.clamp_selection();
+        redundant.len()
     }
+
     fn remove_container(&mut self) {
+        self.container_history.clear();
         if let Selection::Container(index) = self.selected {
-            if index < self.state.fluid_containers.len() {
+            if index < self.state.container_count() {
                 self.state.fluid_containers.remove(index);
                 if index >= 1 {
                     self.selected = Selection::Container(index - 1);
@@ -350,5 +1286,67 @@ impl GameEngine {
         else {
             self.state.fluid_containers.pop();
         }
+        self.clamp_selection();
+    }
+
+    fn remember_container(&mut self, index: usize) {
+        if let Some(container) = self.state.container(index) {
+            self.container_history.entry(index).or_default().push(container.clone());
+        }
+    }
+
+    /// Puts one container back the way it was before its last edit, leaving every other container alone.
+    /// The change itself goes on the normal undo stack, so it can be undone like any other edit.
+    pub fn undo_container(&mut self, index: usize) -> bool {
+        self.needs_redraw = true;
+        if !self.editor_mode || index >= self.state.container_count() {
+            return false;
+        }
+        let Some(previous) = self.container_history.get_mut(&index).and_then(|history| history.pop()) else {
+            return false;
+        };
+        self.push_undo_state();
+        self.state.fluid_containers[index] = previous;
+        true
+    }
+
+    pub fn undo_selected_container(&mut self) -> bool {
+        match self.selected {
+            Selection::Container(index) => self.undo_container(index),
+            _ => false,
+        }
+    }
+
+    /// Pulls a selection that no longer points at anything back in range, or drops it.
+    /// Needed after anything that changes the number of containers.
+    fn clamp_selection(&mut self) {
+        match self.selected {
+            Selection::Container(index) if index >= self.state.container_count() => {
+                self.selected = match self.state.container_count() {
+                    0 => Selection::None,
+                    count => Selection::Container(count - 1),
+                };
+            }
+            Selection::Color(index) if index >= self.swatch_colors.len() => {
+                self.selected = Selection::None;
+            }
+            _ => {}
+        }
+    }
+
+    /// Colors already on the board can always be added. A new one only fits while the board is under
+    /// `GameState::max_colors_allowed`.
+    /// In single letter mode, colors past Z never fit.
+    fn has_room_for_color(&self, color_id: usize) -> bool {
+        if self.single_letter_colors && color_id >= SINGLE_LETTER_COLOR_LIMIT {
+            debug!("Only colors A to Z are allowed in single letter mode.");
+            return false;
+        }
+        let colors = self.state.get_available_colors();
+        if colors.contains(&color_id) || colors.len() < self.state.max_colors_allowed() {
+            return true;
+        }
+        debug!("This board can't hold more than {} colors, add a container first.", self.state.max_colors_allowed());
+        false
     }
 }
@@ -0,0 +1,238 @@
+use crate::model::*;
+use macroquad::prelude::debug;
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+
+/// Shape of a generated puzzle: how many colors, and how big each container is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PuzzleParams {
+    pub color_count: usize,
+    pub capacity: usize,
+    /// Each container's capacity is picked from `capacity - capacity_spread..=capacity + capacity_spread` (at least 1).
+    /// 0 gives the classic board where every container is the same size.
+    pub capacity_spread: usize,
+    /// Each color's amount is picked from `capacity - amount_spread..=capacity + amount_spread` (at least 1),
+    /// separately from the containers, so it may only fit by splitting over several of them.
+    /// Those are the boards the full `is_solvable` is for. 0 gives every color exactly `capacity` packets.
+    pub amount_spread: usize,
+    /// Containers that start with nothing in them. 2 is the classic; fewer is harder and often unsolvable, more gets trivial.
+    pub empty_containers: usize,
+}
+
+/// The size of the daily puzzle, also what a normal launch deals.
+pub const DAILY_PARAMS: PuzzleParams = PuzzleParams {
+    color_count: 5,
+    capacity: 4,
+    capacity_spread: 0,
+    amount_spread: 0,
+    empty_containers: 2,
+};
+const MAX_ATTEMPTS: usize = 100;
+/// Scores below each threshold fall in the matching tier, anything above the last is Expert.
+const TIER_THRESHOLDS: [f32; 3] = [20.0, 45.0, 90.0];
+
+/// How hard a board is, split into parts so each can be weighed separately.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DifficultyEstimate {
+    pub optimal_moves: usize,
+    /// Moves on the optimal path where it was the only sensible move.
+    pub forced_moves: usize,
+    /// Moves on the optimal path where there were other sensible moves to choose from.
+    pub choice_points: usize,
+    /// Sum over the optimal path of the sensible alternatives that weren't taken.
+    /// A long but forced solution scores 0 here.
+    pub choice_score: f32,
+}
+
+/// How much each part of a `DifficultyEstimate` counts toward its score.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DifficultyWeights {
+    pub moves: f32,
+    pub choices: f32,
+}
+
+impl Default for DifficultyWeights {
+    fn default() -> Self {
+        Self { moves: 1.0, choices: 1.0 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DifficultyTier {
+    Easy,
+    Medium,
+    Hard,
+    Expert,
+}
+
+impl DifficultyEstimate {
+    pub fn score(&self, weights: DifficultyWeights) -> f32 {
+        weights.moves * self.optimal_moves as f32 + weights.choices * self.choice_score
+    }
+
+    pub fn tier(&self, weights: DifficultyWeights) -> DifficultyTier {
+        let score = self.score(weights);
+        match TIER_THRESHOLDS.iter().position(|&threshold| score < threshold) {
+            Some(0) => DifficultyTier::Easy,
+            Some(1) => DifficultyTier::Medium,
+            Some(_) => DifficultyTier::Hard,
+            None => DifficultyTier::Expert,
+        }
+    }
+}
+
+impl PuzzleParams {
+    /// Guesses the parameters a board could have been generated with, so a similar one can be dealt.
+    pub fn from_state(state: &GameState) -> PuzzleParams {
+        let sizes = state.get_container_sizes();
+        let min_capacity = sizes.iter().copied().min().unwrap_or(1);
+        let max_capacity = sizes.iter().copied().max().unwrap_or(1);
+        let capacity = (min_capacity + max_capacity).div_ceil(2);
+        let amount_spread = state
+            .get_available_colors_with_count()
+            .iter()
+            .map(|&(_, amount)| amount.abs_diff(capacity))
+            .max()
+            .unwrap_or(0);
+        PuzzleParams {
+            color_count: state.get_available_colors().len(),
+            capacity,
+            capacity_spread: (max_capacity - min_capacity).div_ceil(2),
+            amount_spread,
+            empty_containers: state.fluid_containers.iter().filter(|c| c.is_empty()).count(),
+        }
+    }
+}
+
+impl GameState {
+    pub fn generate(params: PuzzleParams, seed: u64) -> Option<GameState> {
+        // Deals every packet at random into the containers, filling them in turn, adds empty ones, then keeps the first deal the solver can finish.
+        // ChaCha8 is used over the default rng because its output is fixed across platforms and rand versions, so a seed always gives the same board.
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        for attempt in 0..MAX_ATTEMPTS {
+            let state = Self::deal(params, &mut rng);
+            // The full is_solvable check matters for mixed capacities, where liquids may not fit the containers at all.
+            // Boards that solve themselves by just stacking colors together are skipped as too boring.
+            if !state.is_solved()
+                && !state.is_trivially_solved_by_gathering()
+                && state.is_solvable()
+                && state.solve().is_some()
+            {
+                debug!("Generated a solvable puzzle from seed {} on attempt {}.", seed, attempt + 1);
+                return Some(state);
+            }
+        }
+        debug!("Could not generate a solvable puzzle from seed {}.", seed);
+        None
+    }
+
+    /// Everyone gets the same board on the same (year, month, day).
+    /// None only if no solvable deal turned up for that date, which the daily parameters shouldn't allow.
+    pub fn daily(date: (u16, u8, u8)) -> Option<GameState> {
+        let (year, month, day) = date;
+        let seed = year as u64 * 10_000 + month as u64 * 100 + day as u64;
+        Self::generate(DAILY_PARAMS, seed)
+    }
+
+    /// Rates the board by walking its optimal solution. None if it can't be solved.
+    pub fn estimate_difficulty(&self) -> Option<DifficultyEstimate> {
+        let solution = self.solve()?;
+        let mut estimate = DifficultyEstimate {
+            optimal_moves: solution.len(),
+            forced_moves: 0,
+            choice_points: 0,
+            choice_score: 0.0,
+        };
+        let mut state = self.clone();
+        for mv in &solution {
+            let options = state
+                .get_possible_moves()
+                .iter()
+                .filter(|option| !state.is_pointless_move(option))
+                .count();
+            if options > 1 {
+                estimate.choice_points += 1;
+                estimate.choice_score += (options - 1) as f32;
+            } else {
+                estimate.forced_moves += 1;
+            }
+            state.apply_move(mv);
+        }
+        Some(estimate)
+    }
+
+    fn deal(params: PuzzleParams, rng: &mut ChaCha8Rng) -> GameState {
+        let min_capacity = params.capacity.saturating_sub(params.capacity_spread).max(1);
+        let max_capacity = (params.capacity + params.capacity_spread).max(min_capacity);
+        let capacities: Vec<usize> = (0..params.color_count)
+            .map(|_| rng.random_range(min_capacity..=max_capacity))
+            .collect();
+        let min_amount = params.capacity.saturating_sub(params.amount_spread).max(1);
+        let max_amount = (params.capacity + params.amount_spread).max(min_amount);
+        let mut amounts: Vec<usize> = (0..params.color_count)
+            .map(|_| rng.random_range(min_amount..=max_amount))
+            .collect();
+        // The colors fill exactly the containers that aren't meant to start empty, so the free space is a whole
+        // number of containers. Amounts are nudged one packet at a time until they add up.
+        let room: usize = capacities.iter().sum();
+        loop {
+            let total: usize = amounts.iter().sum();
+            let adjustable: Vec<usize> = (0..amounts.len()).filter(|&i| total < room || amounts[i] > 1).collect();
+            let Some(&color_id) = adjustable.choose(rng).filter(|_| total != room) else { break };
+            if total < room {
+                amounts[color_id] += 1;
+            } else {
+                amounts[color_id] -= 1;
+            }
+        }
+        let mut packets: Vec<FluidPacket> = amounts
+            .iter()
+            .enumerate()
+            .flat_map(|(color_id, &amount)| vec![FluidPacket::new(color_id); amount])
+            .collect();
+        packets.shuffle(rng);
+        let mut fluid_containers = vec![];
+        let mut remaining = packets.into_iter();
+        for &capacity in &capacities {
+            let mut container = FluidContainer::new(capacity);
+            for packet in remaining.by_ref().take(capacity) {
+                container.add_fluid(packet);
+            }
+            fluid_containers.push(container);
+        }
+        for _ in 0..params.empty_containers {
+            fluid_containers.push(FluidContainer::new(rng.random_range(min_capacity..=max_capacity)));
+        }
+        GameState {
+            fluid_containers,
+            pour_rule: PourRule::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn daily_boards_stay_the_same() {
+        // Changing these means every player's board for every past and future date changes too
+        let daily = |date| GameState::daily(date).map(|state| state.get_text_representation());
+        assert_eq!(daily((2024, 1, 1)).as_deref(), Some("ACDD\nBCEE\nCCEA\nAEBD\nABBD\n....\n...."));
+        assert_eq!(daily((2025, 12, 31)).as_deref(), Some("ECAC\nADEB\nBBDE\nCDBA\nAEDC\n....\n...."));
+    }
+
+    #[test]
+    fn mixed_sizes_need_the_full_solvability_check() {
+        let params = PuzzleParams { color_count: 5, capacity: 4, capacity_spread: 2, amount_spread: 3, empty_containers: 2 };
+        let mut undecided_by_fast_checks = 0;
+        for seed in 0..5 {
+            let state = GameState::generate(params, seed).expect("mixed-size parameters should be solvable");
+            assert!(state.is_solvable() && state.solve().is_some());
+            if state.fast_is_maybe_solvable().is_none() {
+                undecided_by_fast_checks += 1;
+            }
+        }
+        assert!(undecided_by_fast_checks > 0, "every generated board was settled by the fast checks");
+    }
+}
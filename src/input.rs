@@ -0,0 +1,147 @@
+use macroquad::prelude::*;
+
+// Two clicks closer than this in time and distance are a double click
+const DOUBLE_CLICK_INTERVAL: f64 = 0.3;
+const DOUBLE_CLICK_DISTANCE: f32 = 10.0;
+// A press that moves further than this before it's let go is a drag
+const DRAG_DISTANCE: f32 = 10.0;
+// Holding a finger still this long counts as a right click
+const LONG_PRESS_TIME: f64 = 0.5;
+
+/// What the player did with the mouse or a finger this frame, in screen coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PointerEvent {
+    Click(f32, f32),
+    RightClick(f32, f32),
+    DoubleClick(f32, f32),
+    Drag((f32, f32), (f32, f32)),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TrackedTouch {
+    id: u64,
+    start: (f32, f32),
+    start_time: f64,
+    long_pressed: bool,
+}
+
+/// Turns mouse buttons and touches into the same few events, so the game can be played with either.
+/// A tap is a click, a long press is a right click, and sliding a finger is a drag. Only the first finger
+/// down is followed, others are ignored until it's lifted.
+pub struct PointerInput {
+    drag_start: Option<(f32, f32)>,
+    last_click: Option<(f64, (f32, f32))>,
+    touch: Option<TrackedTouch>,
+}
+
+impl Default for PointerInput {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PointerInput {
+    pub fn new() -> Self {
+        // Touches are handled here, macroquad turning them into mouse clicks as well would count every tap twice
+        simulate_mouse_with_touch(false);
+        Self {
+            drag_start: None,
+            last_click: None,
+            touch: None,
+        }
+    }
+
+    /// Call once per frame.
+    pub fn poll(&mut self) -> Vec<PointerEvent> {
+        let mut events = vec![];
+        self.poll_mouse(&mut events);
+        self.poll_touches(&mut events);
+        events
+    }
+
+    fn poll_mouse(&mut self, events: &mut Vec<PointerEvent>) {
+        if is_mouse_button_pressed(MouseButton::Left) {
+            let position = mouse_position();
+            events.push(self.click_or_double_click(position));
+            self.drag_start = Some(position);
+        }
+        if is_mouse_button_released(MouseButton::Left)
+            && let Some(start) = self.drag_start.take()
+        {
+            let end = mouse_position();
+            if distance(start, end) > DRAG_DISTANCE {
+                events.push(PointerEvent::Drag(start, end));
+            }
+        }
+        if is_mouse_button_pressed(MouseButton::Right) {
+            let (x, y) = mouse_position();
+            events.push(PointerEvent::RightClick(x, y));
+        }
+    }
+
+    fn poll_touches(&mut self, events: &mut Vec<PointerEvent>) {
+        let now = get_time();
+        for touch in touches() {
+            // Touch positions come in physical pixels, unlike the mouse
+            let position = (touch.position.x / miniquad::window::dpi_scale(), touch.position.y / miniquad::window::dpi_scale());
+            match self.touch {
+                None if touch.phase == TouchPhase::Started => {
+                    self.touch = Some(TrackedTouch {
+                        id: touch.id,
+                        start: position,
+                        start_time: now,
+                        long_pressed: false,
+                    });
+                }
+                // Started and ended within one frame, so only the end was seen
+                None if touch.phase == TouchPhase::Ended => {
+                    events.push(self.click_or_double_click(position));
+                }
+                Some(tracked) if tracked.id == touch.id => match touch.phase {
+                    TouchPhase::Ended => {
+                        self.touch = None;
+                        if tracked.long_pressed {
+                            continue;
+                        }
+                        if distance(tracked.start, position) > DRAG_DISTANCE {
+                            events.push(PointerEvent::Drag(tracked.start, position));
+                        } else {
+                            events.push(self.click_or_double_click(tracked.start));
+                        }
+                    }
+                    TouchPhase::Cancelled => {
+                        self.touch = None;
+                    }
+                    _ => {
+                        if !tracked.long_pressed
+                            && now - tracked.start_time >= LONG_PRESS_TIME
+                            && distance(tracked.start, position) <= DRAG_DISTANCE
+                        {
+                            events.push(PointerEvent::RightClick(tracked.start.0, tracked.start.1));
+                            self.touch = Some(TrackedTouch { long_pressed: true, ..tracked });
+                        }
+                    }
+                },
+                _ => {}
+            }
+        }
+    }
+
+    fn click_or_double_click(&mut self, (x, y): (f32, f32)) -> PointerEvent {
+        let now = get_time();
+        let is_double_click = self.last_click.is_some_and(|(time, last)| {
+            now - time < DOUBLE_CLICK_INTERVAL && distance(last, (x, y)) < DOUBLE_CLICK_DISTANCE
+        });
+        if is_double_click {
+            self.last_click = None;
+            PointerEvent::DoubleClick(x, y)
+        } else {
+            self.last_click = Some((now, (x, y)));
+            PointerEvent::Click(x, y)
+        }
+    }
+}
+
+fn distance(a: (f32, f32), b: (f32, f32)) -> f32 {
+    (b.0 - a.0).hypot(b.1 - a.1)
+}
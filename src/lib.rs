@@ -0,0 +1,9 @@
+pub mod gameplay;
+pub mod generator;
+pub mod input;
+pub mod model;
+pub mod pack;
+pub mod packed;
+pub mod renderer;
+pub mod snapshot;
+pub mod solver;
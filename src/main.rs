@@ -1,28 +1,82 @@
-mod gameplay;
-mod model;
-mod renderer;
-mod solver;
-
-
-use crate::gameplay::*;
-use crate::solver::*;
+use water_sort::gameplay::*;
+use water_sort::input::{PointerEvent, PointerInput};
+use water_sort::model::ControlAction;
 
 use macroquad::prelude::*;
 
 #[macroquad::main("Fluid Container Simulation")]
 async fn main() {
-    let mut engine = GameEngine::new(true);
+    let mut engine = GameEngine::new(true, false);
+    let mut input = PointerInput::new();
     loop {
+        engine.update();
         engine.render();
-        if is_mouse_button_pressed(MouseButton::Left) {
-            let (x, y) = mouse_position();
-            engine.handle_click(x, y, false);
+        draw_text(&engine.status_string(), 10.0, screen_height() - 2.0, 16.0, GRAY);
+        let (mouse_x, mouse_y) = mouse_position();
+        engine.handle_hover(mouse_x, mouse_y);
+        for event in input.poll() {
+            match event {
+                PointerEvent::Click(x, y) => engine.handle_click(x, y, false),
+                PointerEvent::RightClick(x, y) => engine.handle_click(x, y, true),
+                PointerEvent::DoubleClick(x, y) => engine.handle_double_click(x, y),
+                PointerEvent::Drag(start, end) => engine.handle_drag(start, end),
+            }
+        }
+        if is_key_pressed(KeyCode::Tab) {
+            let backwards = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+            engine.cycle_container_selection(!backwards, false);
+        }
+        let digit_keys = [
+            KeyCode::Key0, KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4,
+            KeyCode::Key5, KeyCode::Key6, KeyCode::Key7, KeyCode::Key8, KeyCode::Key9,
+        ];
+        for (digit, key) in digit_keys.iter().enumerate() {
+            if is_key_pressed(*key) {
+                // 0 goes back to copying the selected container's capacity
+                engine.set_new_container_capacity(Some(digit));
+            }
+        }
+        let ctrl = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+        let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+        if ctrl && shift && is_key_pressed(KeyCode::C) {
+            engine.copy_compact_state();
+        } else if ctrl && is_key_pressed(KeyCode::C) {
+            engine.handle_game_action(ControlAction::CopyState);
+        }
+        if ctrl && is_key_pressed(KeyCode::V) {
+            engine.handle_game_action(ControlAction::PasteState);
+        }
+        if is_key_pressed(KeyCode::U) {
+            engine.undo_selected_container();
+        }
+        if is_key_pressed(KeyCode::T) {
+            engine.toggle_light_theme();
+        }
+        if is_key_pressed(KeyCode::E) {
+            engine.cycle_empty_packet_style();
+        }
+        if is_key_pressed(KeyCode::D) {
+            engine.toggle_dividers();
+        }
+        if is_key_pressed(KeyCode::I) {
+            engine.toggle_instant_pours();
+        }
+        if is_key_pressed(KeyCode::P) {
+            engine.toggle_perf_overlay();
+        }
+        if is_key_pressed(KeyCode::M) {
+            engine.toggle_tutorial_mode();
+        }
+        if is_key_pressed(KeyCode::G) {
+            engine.toggle_target_panel();
+        }
+        if is_key_pressed(KeyCode::H) {
+            engine.set_hard_mode(!engine.is_hard_mode());
         }
-        if is_mouse_button_pressed(MouseButton::Right) {
-            let (x, y) = mouse_position();
-            engine.handle_click(x, y, true);
+        if is_key_pressed(KeyCode::A) {
+            engine.toggle_auto_select_source();
         }
-        if is_key_pressed(KeyCode::S) {
+        if is_key_pressed(KeyCode::S) && !engine.is_hard_mode() {
             if engine.get_state().is_solvable() {
                 println!("The current state is solvable.");
             } else {
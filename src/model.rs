@@ -1,4 +1,6 @@
 use macroquad::{prelude::*};
+use std::collections::HashMap;
+use std::sync::OnceLock;
 
 // Parallel recursion
 
@@ -39,15 +41,31 @@ pub const FLUID_COLORS: [Color; 32] = [
     Color::new(1.0  , 0.549, 0.0  , 1.0  ), //DARKORANGE
 ];
 
-#[derive(Copy, Clone, PartialEq, Eq, Debug, PartialOrd, Ord)]
+/// Biggest color id a board can hold, longer letter names are read as empty.
+/// Packed boards and snapshots store a color as 2 * id + 4 at most, which has to fit a usize.
+pub(crate) const MAX_COLOR_ID: usize = u32::MAX as usize;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, PartialOrd, Ord)]
 pub enum FluidPacket {
     Empty,
-    Fluid { color_id: usize },
+    /// `revealed` is false for a face-down packet, whose color stays a secret until it's the top of its container.
+    /// Written with a "?" in front, like "?A". Hidden packets stop a pour, only what's been seen can be moved.
+    Fluid { color_id: usize, revealed: bool },
+    /// A stone: takes up a slot, never moves, and nothing can be poured onto it or taken from below it.
+    /// Written as "#". Boards without any behave exactly as before. See `GameState::apply_move` for how they break.
+    Blocked,
+    /// Matches any color: it can go onto anything, anything can go onto it, and it counts as part of whatever
+    /// color run it sits in. Written as "*". Like blocked packets, the rules only change on boards that have one.
+    Wildcard,
 }
 
 impl FluidPacket {
     pub fn new(color_id: usize) -> Self {
-        FluidPacket::Fluid { color_id }
+        FluidPacket::Fluid { color_id, revealed: true }
+    }
+
+    pub fn new_hidden(color_id: usize) -> Self {
+        FluidPacket::Fluid { color_id, revealed: false }
     }
 
     pub fn new_from_repr(repr: &str) -> Self {
@@ -55,11 +73,23 @@ impl FluidPacket {
         if s.is_empty() || s == "." {
             return FluidPacket::Empty;
         }
+        if s == "#" {
+            return FluidPacket::Blocked;
+        }
+        if s == "*" {
+            return FluidPacket::Wildcard;
+        }
+        if let Some(rest) = s.strip_prefix('?') {
+            return match Self::new_from_repr(rest) {
+                FluidPacket::Fluid { color_id, .. } => FluidPacket::new_hidden(color_id),
+                other => other,
+            };
+        }
 
         // Allow multi-character labels: A..Z, AA, AB, ... (Excel-style).
         // Any non A-Z character makes the repr invalid and results in Empty.
         match Self::letters_to_color_id(s) {
-            Some(id) => FluidPacket::Fluid { color_id: id },
+            Some(id) => FluidPacket::new(id),
             None => FluidPacket::Empty,
         }
     }
@@ -91,17 +121,35 @@ impl FluidPacket {
         }
 
         // Back to 0-based.
-        acc.checked_sub(1)
+        acc.checked_sub(1).filter(|&id| id <= MAX_COLOR_ID)
     }
 
     pub fn is_empty(&self) -> bool {
         matches!(self, FluidPacket::Empty)
     }
 
+    pub fn is_blocked(&self) -> bool {
+        matches!(self, FluidPacket::Blocked)
+    }
+
+    pub fn is_wildcard(&self) -> bool {
+        matches!(self, FluidPacket::Wildcard)
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        matches!(self, FluidPacket::Fluid { revealed: false, .. })
+    }
+
+    pub fn reveal(&mut self) {
+        if let FluidPacket::Fluid { revealed, .. } = self {
+            *revealed = true;
+        }
+    }
+
     pub fn get_color_id(&self) -> Option<usize> {
         match self {
-            FluidPacket::Fluid { color_id } => Some(*color_id),
-            FluidPacket::Empty => None,
+            FluidPacket::Fluid { color_id, .. } => Some(*color_id),
+            FluidPacket::Empty | FluidPacket::Blocked | FluidPacket::Wildcard => None,
         }
     }
 
@@ -110,6 +158,12 @@ impl FluidPacket {
         let letter_vec: Vec<u8> = letters.collect();
         let len = letter_vec.len();
 
+        if self.is_blocked() {
+            return "#".to_string();
+        }
+        if self.is_wildcard() {
+            return "*".to_string();
+        }
         let mut chars = Vec::new();
         let mut id = match self.get_color_id() {
             None => return ".".to_string(),
@@ -122,35 +176,94 @@ impl FluidPacket {
             id = (id - 1) / len;
         }
 
+        if self.is_hidden() {
+            chars.push('?');
+        }
         chars.iter().rev().collect()
     }
 
     pub fn get_color(&self) -> Option<Color> {
         match self {
-            FluidPacket::Fluid { color_id } => Some(FLUID_COLORS[color_id % FLUID_COLORS.len()]),
-            FluidPacket::Empty => None,
+            FluidPacket::Fluid { color_id, .. } => Some(FLUID_COLORS[color_id % FLUID_COLORS.len()]),
+            FluidPacket::Empty | FluidPacket::Blocked | FluidPacket::Wildcard => None,
         }
     }
 }
 
+// Pour rules
+
+/// Which pours are allowed, on top of there being room in the target.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum PourRule {
+    /// Liquid can go into an empty container or onto the same color.
+    #[default]
+    Standard,
+    /// Like standard, but a container that holds liquid only ever accepts its dominant color.
+    /// A container becomes free again once it's emptied.
+    OneColorPerContainer,
+    /// Any color can go onto any other, for freely rearranging packets in a sandbox.
+    /// Solving makes no sense without the color rule, so move generation and the solver are off.
+    Free,
+}
+
+impl PourRule {
+    pub fn allows_solving(&self) -> bool {
+        !matches!(self, PourRule::Free)
+    }
+}
+
 // FluidContainer
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug)]
 pub struct FluidContainer {
     packets: Vec<FluidPacket>,
     capacity: usize,
+    /// Free text shown above the container, purely for documenting a puzzle. Equality and hashing ignore it.
+    label: Option<String>,
+    // Filled by get_text_representation, every method that changes the packets or label has to clear it.
+    text_cache: OnceLock<String>,
 }
 
-#[allow(dead_code)]
 impl FluidContainer {
     pub fn new(capacity: usize) -> Self {
         Self {
             packets: vec![FluidPacket::Empty; capacity],
             capacity,
+            label: None,
+            text_cache: OnceLock::new(),
         }
     }
 
+    /// Parses a container with gravity applied: every empty slot is moved above the liquid,
+    /// so "A.B" becomes "AB.". This is what pasting a board uses.
     pub fn new_from_repr(repr: &str) -> Self {
+        let packets = Self::parse_packets(repr);
+        let non_empty_packets: Vec<FluidPacket> = packets.iter().cloned().filter(|p| !p.is_empty()).collect();
+        let empty_count = packets.len() - non_empty_packets.len();
+        let packets: Vec<FluidPacket> = non_empty_packets.into_iter().chain(vec![FluidPacket::Empty; empty_count]).collect();
+        let capacity = packets.len();
+        Self { packets, capacity, label: None, text_cache: OnceLock::new() }
+    }
+
+    /// Parses a container keeping the packets in the literal order they were written, gaps included.
+    /// Meant for authoring tools that need "A.B" to round-trip unchanged.
+    pub fn new_from_repr_exact(repr: &str) -> Self {
+        let packets = Self::parse_packets(repr);
+        let capacity = packets.len();
+        Self { packets, capacity, label: None, text_cache: OnceLock::new() }
+    }
+
+    /// Builds a container from packets listed bottom to top, kept in that exact order.
+    pub fn from_packets(packets: Vec<FluidPacket>) -> Self {
+        let capacity = packets.len();
+        Self { packets, capacity, label: None, text_cache: OnceLock::new() }
+    }
+
+    fn parse_packets(repr: &str) -> Vec<FluidPacket> {
+        // The plain format never has digits, so any digit means the line is run-length encoded
+        if repr.chars().any(|ch| ch.is_ascii_digit()) {
+            return Self::parse_compact_packets(repr);
+        }
         let mut packets = Vec::new();
         let use_commas = repr.contains(',');
         if use_commas {
@@ -162,19 +275,65 @@ impl FluidContainer {
                 packets.push(packet);
             }
         } else {
+            // A "?" hides the packet written right after it
+            let mut hide_next = false;
             for ch in repr.chars() {
-                let packet = FluidPacket::new_from_repr(&ch.to_string());
+                if ch == '?' {
+                    hide_next = true;
+                    continue;
+                }
+                let mut packet = FluidPacket::new_from_repr(&ch.to_string());
+                if hide_next && let Some(color_id) = packet.get_color_id() {
+                    packet = FluidPacket::new_hidden(color_id);
+                }
+                hide_next = false;
                 packets.push(packet);
             }
         }
-        let non_empty_packets: Vec<FluidPacket> = packets.iter().cloned().filter(|p| !p.is_empty()).collect();
-        let empty_count = packets.len() - non_empty_packets.len();
-        let packets: Vec<FluidPacket> = non_empty_packets.into_iter().chain(vec![FluidPacket::Empty; empty_count]).collect();
-        let capacity = packets.len();
-        Self { packets, capacity }
+        packets
+    }
+
+    /// Reads the run-length format: each packet as in the plain format, optionally followed by how many of it
+    /// there are, so "A3-5" is three A's and five empties. Letters are read greedily, "AB3" is three of color AB.
+    fn parse_compact_packets(repr: &str) -> Vec<FluidPacket> {
+        let chars: Vec<char> = repr.chars().collect();
+        let mut packets = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            let start = i;
+            if chars[i] == '?' {
+                i += 1;
+            }
+            if i < chars.len() && chars[i].is_ascii_alphabetic() {
+                while i < chars.len() && chars[i].is_ascii_alphabetic() {
+                    i += 1;
+                }
+            } else if i < chars.len() && !chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            let token: String = chars[start..i].iter().collect();
+            let count_start = i;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            let count = if count_start == i {
+                1
+            } else {
+                let digits: String = chars[count_start..i].iter().collect();
+                digits.parse::<usize>().unwrap_or(MAX_COMPACT_RUN).min(MAX_COMPACT_RUN)
+            };
+            if token.is_empty() {
+                // A count with nothing in front of it
+                continue;
+            }
+            packets.extend(std::iter::repeat_n(FluidPacket::new_from_repr(&token), count));
+        }
+        packets
     }
 
+    /// Never goes below a capacity of 1, an empty-sized container can't be drawn or poured into.
     pub fn resize(&mut self, new_capacity: usize) {
+        let new_capacity = new_capacity.max(1);
         if new_capacity > self.capacity {
             self.packets
                 .extend(vec![FluidPacket::Empty; new_capacity - self.capacity]);
@@ -182,6 +341,7 @@ impl FluidContainer {
             self.packets.truncate(new_capacity);
         }
         self.capacity = new_capacity;
+        self.text_cache.take();
     }
 
     pub fn change_capacity(&mut self, delta: isize) {
@@ -198,30 +358,92 @@ impl FluidContainer {
         for p in &mut self.packets {
             if p.is_empty() {
                 *p = packet;
+                self.text_cache.take();
                 return true;
             }
         }
         false
     }
 
+    /// Tops the container up with copies of one packet, returning how many were added.
+    pub fn fill_with(&mut self, packet: FluidPacket) -> usize {
+        if packet.is_empty() {
+            return 0;
+        }
+        let mut added = 0;
+        while self.add_fluid(packet) {
+            added += 1;
+        }
+        added
+    }
+
     pub fn push_fluid(&mut self, packet: FluidPacket) -> bool {
-        if self.is_empty() || self.get_top_fluid() == packet {
+        let (top_color, depth) = self.top_run();
+        if self.is_empty() || (depth > 0 && colors_match(top_color, packet.get_color_id())) {
             return self.add_fluid(packet);
         }
         false
     }
 
+    /// Takes the top packet out. Returns Empty if there's nothing to take, or if a blocked packet is on top.
     pub fn pop_fluid(&mut self) -> FluidPacket {
         for packet in self.packets.iter_mut().rev() {
-            if let FluidPacket::Fluid { color_id } = packet {
-                let color_id = *color_id;
-                *packet = FluidPacket::Empty;
-                return FluidPacket::Fluid { color_id };
+            match packet {
+                FluidPacket::Fluid { .. } | FluidPacket::Wildcard => {
+                    let popped = std::mem::replace(packet, FluidPacket::Empty);
+                    self.text_cache.take();
+                    return popped;
+                }
+                FluidPacket::Blocked => break,
+                FluidPacket::Empty => {}
             }
         }
         FluidPacket::Empty
     }
 
+    pub fn has_blocked_packets(&self) -> bool {
+        self.packets.iter().any(|p| p.is_blocked())
+    }
+
+    /// Removes the highest blocked packet, letting what was above it settle down. Returns whether there was one.
+    pub fn break_blocked_packet(&mut self) -> bool {
+        let Some(index) = self.packets.iter().rposition(|p| p.is_blocked()) else {
+            return false;
+        };
+        self.packets.remove(index);
+        self.packets.push(FluidPacket::Empty);
+        self.text_cache.take();
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.packets.fill(FluidPacket::Empty);
+        self.text_cache.take();
+    }
+
+    /// Takes out every packet of one color, letting what was above it settle down. Returns how many were removed.
+    pub fn remove_color(&mut self, color_id: usize) -> usize {
+        let before = self.packets.len();
+        // Hidden packets of the color go too, they'd only turn up later otherwise
+        self.packets.retain(|p| p.get_color_id() != Some(color_id));
+        let removed = before - self.packets.len();
+        self.packets.resize(before, FluidPacket::Empty);
+        self.text_cache.take();
+        removed
+    }
+
+    /// Relabels every packet whose color is in the mapping. Colors not in the mapping are left alone.
+    pub fn remap_colors(&mut self, mapping: &HashMap<usize, usize>) {
+        for packet in &mut self.packets {
+            if let FluidPacket::Fluid { color_id, .. } = packet
+                && let Some(new_color_id) = mapping.get(color_id)
+            {
+                *color_id = *new_color_id;
+            }
+        }
+        self.text_cache.take();
+    }
+
     pub fn is_full(&self) -> bool {
         self.packets.iter().all(|p| !p.is_empty())
     }
@@ -238,6 +460,16 @@ impl FluidContainer {
         self.packets.iter().filter(|p| p.is_empty()).count()
     }
 
+    pub fn get_label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+
+    /// Blank labels are dropped.
+    pub fn set_label(&mut self, label: Option<&str>) {
+        self.label = label.map(str::trim).filter(|l| !l.is_empty()).map(str::to_string);
+        self.text_cache.take();
+    }
+
     pub fn get_capacity(&self) -> usize {
         self.capacity
     }
@@ -246,6 +478,14 @@ impl FluidContainer {
         self.get_capacity() - self.get_empty_space()
     }
 
+    /// Fraction of the container that holds liquid, from 0.0 to 1.0. A zero-capacity container is 0.0.
+    pub fn fill_fraction(&self) -> f32 {
+        if self.capacity == 0 {
+            return 0.0;
+        }
+        self.get_filled_amount() as f32 / self.capacity as f32
+    }
+
     pub fn get_entropy(&self) -> usize {
         // Entropy in the entire system always strictly decreases with valid moves, unless pouring between two containers of the same color.
         // Entropy is the number of color transitions in the container, plus one for each contiguous block of color.
@@ -254,7 +494,7 @@ impl FluidContainer {
         let mut prev_color_id: Option<usize> = None;
         for packet in &self.packets {
             match packet {
-                FluidPacket::Fluid { color_id } => {
+                FluidPacket::Fluid { color_id, .. } => {
                     if Some(*color_id) != prev_color_id {
                         entropy += 1;
                         if prev_color_id.is_some() {
@@ -263,49 +503,127 @@ impl FluidContainer {
                         prev_color_id = Some(*color_id);
                     }
                 }
-                FluidPacket::Empty => {
+                FluidPacket::Empty | FluidPacket::Blocked => {
                     prev_color_id = None;
                 }
+                // Blends into whatever run it's in
+                FluidPacket::Wildcard => {}
             }
         }
         entropy
     }
 
+    /// Iterates over `(color_id, depth)` for each block of a single color, bottom to top.
+    /// Empty space is skipped, and also splits blocks if there's a gap in the middle.
+    pub fn iter_runs(&self) -> PacketRuns<'_> {
+        PacketRuns {
+            packets: self.packets.iter().peekable(),
+        }
+    }
+
+    pub fn get_longest_run(&self) -> usize {
+        // Length of the longest block of a single color anywhere in the container.
+        self.iter_runs().map(|(_, depth)| depth).max().unwrap_or(0)
+    }
+
+    /// The highest packet that isn't empty space. This can be Blocked, which pours nowhere and accepts nothing.
     pub fn get_top_fluid(&self) -> FluidPacket {
         for packet in self.packets.iter().rev() {
-            if let FluidPacket::Fluid { .. } = packet {
+            if !packet.is_empty() {
                 return *packet;
             }
         }
         FluidPacket::Empty
     }
 
+    /// 0 when a blocked packet is on top, since nothing can be poured out from under it.
     pub fn get_top_fluid_depth(&self) -> usize {
+        self.top_run().1
+    }
+
+    /// The color and depth of the run that would be poured out: the top packets down to the first gap,
+    /// blocked packet, or change of color. Wildcards join the run, so the color is None only when the run is all
+    /// wildcards or there's nothing to pour.
+    fn top_run(&self) -> (Option<usize>, usize) {
+        let mut color = None;
         let mut depth = 0;
-        let mut packets = self.packets.iter().rev();
-        let top_color_id = loop {
-            match packets.next() {
-                Some(FluidPacket::Empty) => continue,
-                Some(FluidPacket::Fluid { color_id }) => break *color_id,
-                None => return 0,
-            }
-        };
-        depth += 1;
-        for packet in packets {
+        for packet in self.packets.iter().rev() {
             match packet {
-                FluidPacket::Fluid { color_id } if *color_id == top_color_id => depth += 1,
-                _ => break,
+                FluidPacket::Empty if depth == 0 => continue,
+                FluidPacket::Empty | FluidPacket::Blocked | FluidPacket::Fluid { revealed: false, .. } => break,
+                FluidPacket::Wildcard => depth += 1,
+                FluidPacket::Fluid { color_id, .. } => {
+                    if color.is_some_and(|c| c != *color_id) {
+                        break;
+                    }
+                    color = Some(*color_id);
+                    depth += 1;
+                }
+            }
+        }
+        (color, depth)
+    }
+
+    pub fn has_wildcards(&self) -> bool {
+        self.packets.iter().any(|p| p.is_wildcard())
+    }
+
+    pub fn has_hidden_packets(&self) -> bool {
+        self.packets.iter().any(|p| p.is_hidden())
+    }
+
+    /// Turns the top packet face up if it's hidden. Returns whether anything was revealed.
+    pub fn reveal_top(&mut self) -> bool {
+        match self.packets.iter_mut().rev().find(|p| !p.is_empty()) {
+            Some(packet) if packet.is_hidden() => {
+                packet.reveal();
+                self.text_cache.take();
+                true
             }
+            _ => false,
         }
-        depth
     }
 
     pub fn get_packets(&self) -> &Vec<FluidPacket> {
         &self.packets
     }
 
+    /// The color with the most packets in this container, ties going to the one nearest the bottom.
+    pub fn dominant_color(&self) -> Option<usize> {
+        let mut counts: Vec<(usize, usize)> = vec![];
+        for packet in &self.packets {
+            if let FluidPacket::Fluid { color_id, .. } = packet {
+                match counts.iter_mut().find(|(id, _)| id == color_id) {
+                    Some((_, count)) => *count += 1,
+                    None => counts.push((*color_id, 1)),
+                }
+            }
+        }
+        let mut dominant: Option<(usize, usize)> = None;
+        for (color_id, count) in counts {
+            if dominant.is_none_or(|(_, best)| count > best) {
+                dominant = Some((color_id, count));
+            }
+        }
+        dominant.map(|(color_id, _)| color_id)
+    }
+
     pub fn get_pourable_amount(&self, other: &FluidContainer) -> usize {
-        if self.get_top_fluid() != other.get_top_fluid() && !other.is_empty() {
+        self.get_pourable_amount_with_rule(other, PourRule::Standard)
+    }
+
+    pub fn get_pourable_amount_with_rule(&self, other: &FluidContainer, rule: PourRule) -> usize {
+        if other.get_top_fluid().is_blocked() {
+            return 0;
+        }
+        let top_color = self.top_run().0;
+        if rule != PourRule::Free && !other.is_empty() && !colors_match(top_color, other.top_run().0) {
+            return 0;
+        }
+        if rule == PourRule::OneColorPerContainer
+            && !other.is_empty()
+            && top_color.is_some_and(|color_id| other.dominant_color() != Some(color_id))
+        {
             return 0;
         }
         let depth = self.get_top_fluid_depth();
@@ -313,18 +631,42 @@ impl FluidContainer {
         depth.min(space)
     }
 
+    /// Whether a packet of this color could be poured on top under the standard rule.
+    pub fn can_accept(&self, color_id: usize) -> bool {
+        self.remaining_space_for(color_id) > 0
+    }
+
+    /// How many packets of this color could still be poured in, 0 if another color is on top.
+    pub fn remaining_space_for(&self, color_id: usize) -> usize {
+        let (top_color, depth) = self.top_run();
+        if !self.is_empty() && (depth == 0 || !colors_match(top_color, Some(color_id))) {
+            return 0;
+        }
+        self.get_empty_space()
+    }
+
     pub fn could_pour_into(&self, other: &FluidContainer) -> bool {
         self.get_pourable_amount(other) > 0
     }
 
-    pub fn pour_into(&mut self, other: &mut FluidContainer) -> bool {
-        let transfer_amount = self.get_pourable_amount(other);
+    pub fn could_pour_into_with_rule(&self, other: &FluidContainer, rule: PourRule) -> bool {
+        self.get_pourable_amount_with_rule(other, rule) > 0
+    }
+
+    pub fn pour_into(&mut self, other: &mut FluidContainer, rule: PourRule) -> bool {
+        let transfer_amount = self.get_pourable_amount_with_rule(other, rule);
         if transfer_amount == 0 {
             return false;
         }
         for _ in 0..transfer_amount {
             let packet = self.pop_fluid();
-            other.push_fluid(packet);
+            let landed = if rule == PourRule::Free {
+                other.add_fluid(packet)
+            } else {
+                other.push_fluid(packet)
+            };
+            // The amount was worked out from the same runs, a packet that doesn't land would just be lost
+            debug_assert!(landed, "poured more than the destination could take");
         }
         true
     }
@@ -333,44 +675,136 @@ impl FluidContainer {
         self.get_reverse_pourable_amount(other) > 0
     }
 
+    /// How much of the top run could have been poured in here by a forward move, and so can be taken back out.
+    /// A forward pour only lands on the same color or an empty container, so if anything sits below the top run,
+    /// at least one packet of the run must have been there already and is left behind.
+    /// If the top run is the whole fill (a single packet, or a full one-color container) all of it can go.
     pub fn get_reverse_pourable_amount(&self, other: &FluidContainer) -> usize {
         let space = other.get_empty_space();
-        let mut self_depth = self.get_top_fluid_depth();
-        // Need to leave at least one packet behind to pour back, or empty.
-        if self_depth != self.get_filled_amount() {
-            self_depth -= 1;
+        let self_depth = self.get_top_fluid_depth();
+        if self_depth == 0 || other.get_top_fluid().is_blocked() {
+            // Nothing is ever poured onto a blocked packet, so nothing can be taken back from one either
+            return 0;
         }
-        space.min(self_depth)
+        let movable = if self_depth == self.get_filled_amount() {
+            self_depth
+        } else {
+            // Never underflows: with something below the top run, the run is at least one packet deep
+            self_depth - 1
+        };
+        space.min(movable)
     }
 
-    pub fn reverse_pour_into(&mut self, other: &mut FluidContainer, amount: usize) -> bool {
+    /// Moves up to `amount` packets, as many as the reverse pour rule allows. Returns how many moved.
+    pub fn reverse_pour_into(&mut self, other: &mut FluidContainer, amount: usize) -> usize {
         let transfer_amount = self.get_reverse_pourable_amount(other).min(amount);
-        if transfer_amount == 0 {
-            return false;
-        }
         for _ in 0..transfer_amount {
             let packet = self.pop_fluid();
             other.add_fluid(packet);
         }
-        true
+        transfer_amount
     }
 
-    pub fn get_text_representation(&self) -> String {
+    /// Built once and kept until the container next changes, so copying a big board repeatedly stays cheap.
+    pub fn get_text_representation(&self) -> &str {
+        self.text_cache.get_or_init(|| self.build_text_representation())
+    }
+
+    fn build_text_representation(&self) -> String {
         let mut repr = vec![];
         for packet in &self.packets {
             repr.push(packet.get_letter_representation());
         }
-        let has_multi_char = repr.iter().any(|s| s.len() > 1);
+        // A "?" in front of one letter reads back fine without commas, only colors past Z need them
+        let has_multi_char = repr.iter().any(|s| s.trim_start_matches('?').len() > 1);
         let separator = if has_multi_char { "," } else { "" };
         let mut result = repr.join(separator);
         if has_multi_char && repr.len() == 1 {
             // Ensures that a value like "AB" is interpreted as [AB] and not [A, B].
             result = format!("{},", result);
         }
+        if let Some(label) = &self.label {
+            result = format!("{}|{}", result, label);
+        }
+        result
+    }
+
+    /// The run-length form read by the parser, like "AB2-5" for A, B, B and five empties.
+    /// Much shorter than the plain form for big, mostly empty containers.
+    pub fn get_compact_text_representation(&self) -> String {
+        let mut runs: Vec<(FluidPacket, usize)> = vec![];
+        for packet in &self.packets {
+            match runs.last_mut() {
+                Some((last, count)) if last == packet && *count < MAX_COMPACT_RUN => *count += 1,
+                _ => runs.push((*packet, 1)),
+            }
+        }
+        let mut result = String::new();
+        for (i, (packet, count)) in runs.iter().enumerate() {
+            let symbol = if packet.is_empty() {
+                "-".to_string()
+            } else {
+                packet.get_letter_representation()
+            };
+            // Letters run together, so a count is needed whenever more letters follow or there are several of them
+            let next_starts_with_letter = runs
+                .get(i + 1)
+                .is_some_and(|(next, _)| next.get_color_id().is_some() && !next.is_hidden());
+            let multi_letter = symbol.trim_start_matches('?').len() > 1;
+            result.push_str(&symbol);
+            if *count > 1 || multi_letter || next_starts_with_letter {
+                result.push_str(&count.to_string());
+            }
+        }
+        if let Some(label) = &self.label {
+            result = format!("{}|{}", result, label);
+        }
         result
     }
 }
 
+/// Two run colors can share a container. A run that's all wildcards (None) goes with anything.
+fn colors_match(a: Option<usize>, b: Option<usize>) -> bool {
+    a.is_none() || b.is_none() || a == b
+}
+
+pub struct PacketRuns<'a> {
+    packets: std::iter::Peekable<std::slice::Iter<'a, FluidPacket>>,
+}
+
+impl Iterator for PacketRuns<'_> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<(usize, usize)> {
+        let color_id = loop {
+            match self.packets.next()? {
+                FluidPacket::Fluid { color_id, .. } => break *color_id,
+                FluidPacket::Empty | FluidPacket::Blocked | FluidPacket::Wildcard => continue,
+            }
+        };
+        let mut depth = 1;
+        while self.packets.next_if(|p| p.get_color_id() == Some(color_id)).is_some() {
+            depth += 1;
+        }
+        Some((color_id, depth))
+    }
+}
+
+impl PartialEq for FluidContainer {
+    fn eq(&self, other: &Self) -> bool {
+        self.packets == other.packets && self.capacity == other.capacity
+    }
+}
+
+impl Eq for FluidContainer {}
+
+impl std::hash::Hash for FluidContainer {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.packets.hash(state);
+        self.capacity.hash(state);
+    }
+}
+
 impl PartialOrd for FluidContainer {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         Some(self.cmp(other))
@@ -385,6 +819,33 @@ impl Ord for FluidContainer {
 
 // Game state / moves
 
+/// Bumped whenever the board text format changes in a way older readers would get wrong.
+pub const FORMAT_VERSION: u32 = 3;
+/// Longest run one count in the compact format can describe, so a typo can't ask for billions of packets.
+const MAX_COMPACT_RUN: usize = 1000;
+/// Biggest capacity a `cap=` directive can ask for, for the same reason.
+const MAX_DIRECTIVE_CAPACITY: usize = 1000;
+
+/// Summary numbers for a board, gathered together for stats panels and logging. See `GameState::stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoardStats {
+    pub container_count: usize,
+    pub total_capacity: usize,
+    pub filled: usize,
+    pub empty_spaces: usize,
+    pub distinct_colors: usize,
+    pub max_run_length: usize,
+}
+
+/// How one container differs between two boards, counted in packets.
+/// Packets below the first difference are shared, everything above it counts as removed from the old container and added in the new one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContainerDiff {
+    pub index: usize,
+    pub added: usize,
+    pub removed: usize,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct MoveAction {
     pub from_container: usize,
@@ -392,24 +853,222 @@ pub struct MoveAction {
     pub amount: usize,
 }
 
+impl MoveAction {
+    /// A short form like "3->7 x2": pour from container 3 into container 7, moving 2 packets.
+    /// Indices start at 0, the same as everywhere else.
+    pub fn to_notation(&self) -> String {
+        format!("{}->{} x{}", self.from_container, self.to_container, self.amount)
+    }
+
+    /// Reads back what `to_notation` writes. Spaces around the parts are ignored.
+    pub fn from_notation(notation: &str) -> Option<MoveAction> {
+        let (containers, amount) = notation.split_once('x')?;
+        let (from, to) = containers.split_once("->")?;
+        Some(MoveAction {
+            from_container: from.trim().parse().ok()?,
+            to_container: to.trim().parse().ok()?,
+            amount: amount.trim().parse().ok()?,
+        })
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct GameState {
     pub fluid_containers: Vec<FluidContainer>,
+    pub pour_rule: PourRule,
 }
 
-#[allow(dead_code)]
 impl GameState {
+    /// Parses one container per line with gravity applied, see `FluidContainer::new_from_repr`.
+    /// Paste uses this.
     pub fn new_from_repr(repr: &str) -> Self {
+        Self::parse_lines(repr, FluidContainer::new_from_repr)
+    }
+
+    /// Parses one container per line keeping gaps where they were written.
+    pub fn new_from_repr_exact(repr: &str) -> Self {
+        Self::parse_lines(repr, FluidContainer::new_from_repr_exact)
+    }
+
+    /// Builds a board without going through text. Each inner vec is one container listed bottom to top,
+    /// with None for an empty slot, and its length is the capacity. Order is kept exactly, gaps included.
+    /// Zero-length containers are dropped, the same as blank lines when pasting.
+    pub fn from_grid_2d(grid: Vec<Vec<Option<usize>>>) -> Self {
+        let fluid_containers = grid
+            .into_iter()
+            .filter(|column| !column.is_empty())
+            .map(|column| {
+                FluidContainer::from_packets(
+                    column
+                        .into_iter()
+                        .map(|slot| slot.map_or(FluidPacket::Empty, FluidPacket::new))
+                        .collect(),
+                )
+            })
+            .collect();
+        Self {
+            fluid_containers,
+            pour_rule: PourRule::default(),
+        }
+    }
+
+    /// Like `get_text_representation`, but with each container in the run-length form.
+    pub fn get_compact_text_representation(&self) -> String {
+        self.fluid_containers
+            .iter()
+            .map(|c| c.get_compact_text_representation())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// The text format with a `version=N` header line in front, which is what Copy writes.
+    /// Boards without the header are read as the original, unversioned format.
+    pub fn get_versioned_text_representation(&self) -> String {
+        format!("version={}\n{}", FORMAT_VERSION, self.get_text_representation())
+    }
+
+    fn parse_version_header(line: &str) -> Option<u32> {
+        line.strip_prefix("version=")?.trim().parse().ok()
+    }
+
+    fn parse_lines(repr: &str, parse_container: fn(&str) -> FluidContainer) -> Self {
+        // Capacity is normally the length of the line, but it can be given instead:
+        // a "cap=4" line sets the default for every line after it, and "cap=4 AB" sets it for one line.
+        // Short lines are padded with empty space, longer ones keep all their liquid.
+        // Anything after a "|" is the container's label, as in "AB..|goal".
         let mut fluid_containers: Vec<FluidContainer> = Vec::new();
+        let mut default_capacity: Option<usize> = None;
 
-        for line in repr.lines() {
-            let container = FluidContainer::new_from_repr(line);
+        let mut lines = repr.lines().peekable();
+        if let Some(version) = lines.peek().and_then(|line| Self::parse_version_header(line.trim())) {
+            if version > FORMAT_VERSION {
+                debug!("Board was saved as version {}, newer than {}. Reading it anyway.", version, FORMAT_VERSION);
+            }
+            lines.next();
+        }
+        for line in lines {
+            let trimmed = line.trim();
+            if let Some(capacity) = Self::parse_capacity_directive(trimmed) {
+                default_capacity = Some(capacity);
+                continue;
+            }
+            let (line_capacity, body) = match trimmed.split_once(' ') {
+                Some((directive, rest)) => match Self::parse_capacity_directive(directive) {
+                    Some(capacity) => (Some(capacity), rest),
+                    None => (None, line),
+                },
+                None => (None, line),
+            };
+            let (body, label) = match body.split_once('|') {
+                Some((body, label)) => (body, Some(label)),
+                None => (body, None),
+            };
+            let mut container = parse_container(body);
+            container.set_label(label);
+            if let Some(capacity) = line_capacity.or(default_capacity)
+                && capacity > container.get_capacity()
+                && !body.is_empty()
+            {
+                container.resize(capacity);
+            }
             if container.get_capacity() == 0 {
                 continue;
             }
             fluid_containers.push(container);
         }
-        Self { fluid_containers }
+        let mut state = Self {
+            fluid_containers,
+            pour_rule: PourRule::default(),
+        };
+        state.reveal_tops();
+        state
+    }
+
+    fn parse_capacity_directive(token: &str) -> Option<usize> {
+        let digits = token.strip_prefix("cap=")?.trim();
+        if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        // Too many digits to fit a usize is still a capacity, just a far too big one
+        Some(digits.parse::<usize>().unwrap_or(MAX_DIRECTIVE_CAPACITY).min(MAX_DIRECTIVE_CAPACITY))
+    }
+
+    pub fn stats(&self) -> BoardStats {
+        let total_capacity = self.fluid_containers.iter().map(|c| c.get_capacity()).sum();
+        let empty_spaces = self.get_empty_spaces_count();
+        BoardStats {
+            container_count: self.container_count(),
+            total_capacity,
+            filled: total_capacity - empty_spaces,
+            empty_spaces,
+            distinct_colors: self.get_available_colors().len(),
+            max_run_length: self.fluid_containers.iter().map(|c| c.get_longest_run()).max().unwrap_or(0),
+        }
+    }
+
+    /// The most distinct colors a board laid out like this one should hold.
+    /// Each color ends up filling a container of the typical (most common) capacity, and like the classic layouts
+    /// two such containers' worth of slots stay empty as buffer space, so this is
+    /// `(total slots - 2 * typical capacity) / typical capacity`, further capped at two less than the container count.
+    /// A classic 14 beaker board of 4 packets each comes out at its usual 12 colors.
+    pub fn max_colors_allowed(&self) -> usize {
+        let mut capacity_counts: Vec<(usize, usize)> = vec![];
+        for container in &self.fluid_containers {
+            let capacity = container.get_capacity();
+            if let Some((_, count)) = capacity_counts.iter_mut().find(|(c, _)| *c == capacity) {
+                *count += 1;
+            } else {
+                capacity_counts.push((capacity, 1));
+            }
+        }
+        let Some(typical_capacity) = capacity_counts
+            .iter()
+            .max_by_key(|(capacity, count)| (*count, *capacity))
+            .map(|(capacity, _)| *capacity)
+            .filter(|capacity| *capacity > 0)
+        else {
+            return 0;
+        };
+        let total_slots: usize = self.fluid_containers.iter().map(|c| c.get_capacity()).sum();
+        let by_slots = total_slots.saturating_sub(2 * typical_capacity) / typical_capacity;
+        by_slots.min(self.container_count().saturating_sub(2))
+    }
+
+    pub fn container_count(&self) -> usize {
+        self.fluid_containers.len()
+    }
+
+    pub fn container(&self, index: usize) -> Option<&FluidContainer> {
+        self.fluid_containers.get(index)
+    }
+
+    pub fn container_mut(&mut self, index: usize) -> Option<&mut FluidContainer> {
+        self.fluid_containers.get_mut(index)
+    }
+
+    /// Deletes every empty container and returns `(old_index, new_index)` for each one that's left, in order,
+    /// for fixing up moves or selections that point into the board. Indices missing from it were removed.
+    pub fn remove_empty_containers(&mut self) -> Vec<(usize, usize)> {
+        let mut mapping = vec![];
+        let mut old_index = 0;
+        self.fluid_containers.retain(|container| {
+            let keep = !container.is_empty();
+            if keep {
+                mapping.push((old_index, mapping.len()));
+            }
+            old_index += 1;
+            keep
+        });
+        mapping
+    }
+
+    /// Trades the places of two containers. False, leaving the board alone, if either index is out of range.
+    pub fn swap_containers(&mut self, a: usize, b: usize) -> bool {
+        if a >= self.container_count() || b >= self.container_count() {
+            return false;
+        }
+        self.fluid_containers.swap(a, b);
+        true
     }
 
     pub fn get_text_representation(&self) -> String {
@@ -418,7 +1077,7 @@ impl GameState {
             if i > 0 {
                 out.push('\n');
             }
-            out.push_str(&c.get_text_representation());
+            out.push_str(c.get_text_representation());
         }
         out
     }
@@ -427,7 +1086,7 @@ impl GameState {
         let mut colors = vec![];
         for container in &self.fluid_containers {
             for packet in container.get_packets() {
-                if let FluidPacket::Fluid { color_id } = packet && !colors.contains(color_id) {
+                if let FluidPacket::Fluid { color_id, .. } = packet && !colors.contains(color_id) {
                     colors.push(*color_id);
                 }
             }
@@ -435,11 +1094,25 @@ impl GameState {
         colors
     }
 
+    /// `(container_index, packet_index)` of every face up packet of the color, bottom to top within each container.
+    /// Hidden packets are left out, so highlighting with this doesn't give them away.
+    pub fn color_positions(&self, color_id: usize) -> Vec<(usize, usize)> {
+        let mut positions = vec![];
+        for (container_index, container) in self.fluid_containers.iter().enumerate() {
+            for (packet_index, packet) in container.get_packets().iter().enumerate() {
+                if let FluidPacket::Fluid { color_id: id, revealed: true } = packet && *id == color_id {
+                    positions.push((container_index, packet_index));
+                }
+            }
+        }
+        positions
+    }
+
     pub fn get_available_colors_with_count(&self) -> Vec<(usize, usize)> {
         let mut color_counts = vec![];
         for container in &self.fluid_containers {
             for packet in container.get_packets() {
-                if let FluidPacket::Fluid { color_id } = packet {
+                if let FluidPacket::Fluid { color_id, .. } = packet {
                     if let Some((_, count)) = color_counts.iter_mut().find(|(id, _)| *id == *color_id) {
                         *count += 1;
                     } else {
@@ -451,10 +1124,69 @@ impl GameState {
         color_counts
     }
 
+    /// Colors that are done: all of the color sits in one container with nothing else in it, every packet face up.
+    /// The container doesn't have to be full, a color smaller than every container still counts. Sorted by color id.
+    pub fn completed_colors(&self) -> Vec<usize> {
+        let mut completed: Vec<usize> = self
+            .get_available_colors_with_count()
+            .into_iter()
+            .filter(|&(color_id, count)| {
+                self.fluid_containers.iter().any(|c| {
+                    !c.has_hidden_packets()
+                        && c.get_filled_amount() == count
+                        && c.iter_runs().eq(std::iter::once((color_id, count)))
+                })
+            })
+            .map(|(color_id, _)| color_id)
+            .collect();
+        completed.sort();
+        completed
+    }
+
+    /// What the goal looks like: one container per color, exactly full, in color order.
+    pub fn solved_target(&self) -> Vec<FluidContainer> {
+        let mut colors = self.get_available_colors_with_count();
+        colors.sort();
+        colors
+            .into_iter()
+            .map(|(color_id, count)| FluidContainer::from_packets(vec![FluidPacket::new(color_id); count]))
+            .collect()
+    }
+
+    /// Returns a copy of the board with its colors relabeled, for making different looking variants of one layout.
+    /// The mapping has to cover every color on the board and send no two of them to the same color,
+    /// otherwise colors would merge and the puzzle would change, so None is returned instead.
+    pub fn remap_colors(&self, mapping: &HashMap<usize, usize>) -> Option<GameState> {
+        let used_colors = self.get_available_colors();
+        let mut targets = vec![];
+        for color_id in &used_colors {
+            let target = mapping.get(color_id)?;
+            if targets.contains(target) {
+                return None;
+            }
+            targets.push(*target);
+        }
+        let mut remapped = self.clone();
+        for container in &mut remapped.fluid_containers {
+            container.remap_colors(mapping);
+        }
+        Some(remapped)
+    }
+
     pub fn get_empty_spaces_count(&self) -> usize {
         self.fluid_containers.iter().map(|c| c.get_empty_space()).sum()
     }
 
+    /// Fraction of all slots on the board that hold liquid. A board with no slots is 0.0.
+    pub fn overall_fill_fraction(&self) -> f32 {
+        let total_capacity: usize = self.fluid_containers.iter().map(|c| c.get_capacity()).sum();
+        if total_capacity == 0 {
+            return 0.0;
+        }
+        let filled: usize = self.fluid_containers.iter().map(|c| c.get_filled_amount()).sum();
+        filled as f32 / total_capacity as f32
+    }
+
     pub fn get_entropy(&self) -> usize {
         self.fluid_containers.iter().map(|c| c.get_entropy()).sum()
     }
@@ -463,7 +1195,7 @@ impl GameState {
         let mut colors = vec![];
         for container in &self.fluid_containers {
             let packet = container.get_top_fluid();
-            if let FluidPacket::Fluid { color_id } = packet {
+            if let FluidPacket::Fluid { color_id, .. } = packet {
                 colors.push(color_id);
             }
         }
@@ -479,29 +1211,104 @@ impl GameState {
         sizes
     }
 
-    pub fn apply_move(&mut self, action: &MoveAction) {
+    pub fn could_apply_move(&self, from: usize, to: usize) -> bool {
+        if !self.move_indices_valid(from, to) {
+            return false;
+        }
+        self.fluid_containers[from].could_pour_into_with_rule(&self.fluid_containers[to], self.pour_rule)
+    }
+
+    /// The one container that could pour into `to` right now, or None if there are none or several to choose from.
+    pub fn unique_source_for(&self, to: usize) -> Option<usize> {
+        let mut sources = (0..self.fluid_containers.len()).filter(|&from| self.could_apply_move(from, to));
+        let source = sources.next()?;
+        if sources.next().is_some() {
+            return None;
+        }
+        Some(source)
+    }
+
+    fn move_indices_valid(&self, from: usize, to: usize) -> bool {
+        let count = self.fluid_containers.len();
+        from != to && from < count && to < count
+    }
+
+    /// Returns false without touching the board if nothing could be poured,
+    /// including when the move points at containers that don't exist (like a stale solution after editing).
+    /// A pour that completes a container (fills it with one color) breaks the highest blocked packet in every container.
+    pub fn apply_move(&mut self, action: &MoveAction) -> bool {
         let from = action.from_container;
         let to = action.to_container;
-        if from < to {
+        if !self.move_indices_valid(from, to) {
+            debug!("Ignoring {} on a board with {} containers.", action.to_notation(), self.fluid_containers.len());
+            return false;
+        }
+        let poured = if from < to {
             let (left, right) = self.fluid_containers.split_at_mut(to);
-            left[from].pour_into(&mut right[0])
+            left[from].pour_into(&mut right[0], self.pour_rule)
         } else {
             let (left, right) = self.fluid_containers.split_at_mut(from);
-            right[0].pour_into(&mut left[to])
+            right[0].pour_into(&mut left[to], self.pour_rule)
         };
+        let target = &self.fluid_containers[to];
+        if poured && target.is_full() && target.get_top_fluid_depth() == target.get_capacity() {
+            for container in &mut self.fluid_containers {
+                container.break_blocked_packet();
+            }
+        }
+        if poured {
+            self.reveal_tops();
+        }
+        poured
+    }
+
+    /// False while any packet is still face down. The exact solver only runs on fully revealed boards,
+    /// planning around colors the player can't see would be cheating.
+    pub fn is_fully_revealed(&self) -> bool {
+        !self.fluid_containers.iter().any(|c| c.has_hidden_packets())
+    }
+
+    /// Whatever is on top of a container can always be seen.
+    pub fn reveal_tops(&mut self) {
+        for container in &mut self.fluid_containers {
+            container.reveal_top();
+        }
+    }
+
+    pub fn has_blocked_packets(&self) -> bool {
+        self.fluid_containers.iter().any(|c| c.has_blocked_packets())
+    }
+
+    pub fn has_wildcards(&self) -> bool {
+        self.fluid_containers.iter().any(|c| c.has_wildcards())
+    }
+
+    /// The board as it will be once every blocked packet has broken.
+    pub fn without_blocked_packets(&self) -> GameState {
+        let mut state = self.clone();
+        for container in &mut state.fluid_containers {
+            while container.break_blocked_packet() {}
+        }
+        state
     }
 
-    pub fn apply_reverse_move(&mut self, action: &MoveAction) {
+    /// Returns how many packets actually moved. That's fewer than `action.amount` when more was asked for than
+    /// the reverse pour rule allows, and 0 when nothing could move at all.
+    pub fn apply_reverse_move(&mut self, action: &MoveAction) -> usize {
         let from = action.from_container;
         let to = action.to_container;
         let amount = action.amount;
+        if !self.move_indices_valid(from, to) {
+            debug!("Ignoring reverse {} on a board with {} containers.", action.to_notation(), self.fluid_containers.len());
+            return 0;
+        }
         if from < to {
             let (left, right) = self.fluid_containers.split_at_mut(to);
-            left[from].reverse_pour_into(&mut right[0], amount);
+            left[from].reverse_pour_into(&mut right[0], amount)
         } else {
             let (left, right) = self.fluid_containers.split_at_mut(from);
-            right[0].reverse_pour_into(&mut left[to], amount);
-        };
+            right[0].reverse_pour_into(&mut left[to], amount)
+        }
     }
 
     pub fn get_sorted_containers(&self) -> Vec<FluidContainer> {
@@ -513,8 +1320,38 @@ impl GameState {
     pub fn is_solved(&self) -> bool {
         self.fluid_containers.iter().all(|c| c.is_solved())
     }
+
+    /// Containers that differ from `other`, matched up by position. A container missing on one side counts as empty.
+    pub fn diff(&self, other: &GameState) -> Vec<ContainerDiff> {
+        let count = self.container_count().max(other.container_count());
+        let mut diffs = vec![];
+        for index in 0..count {
+            let before = self.container(index);
+            let after = other.container(index);
+            if before == after {
+                continue;
+            }
+            let before_packets = before.map_or(&[][..], |c| c.get_packets());
+            let after_packets = after.map_or(&[][..], |c| c.get_packets());
+            let shared = before_packets.iter().zip(after_packets).take_while(|(a, b)| a == b).count();
+            diffs.push(ContainerDiff {
+                index,
+                added: after_packets[shared..].iter().filter(|p| !p.is_empty()).count(),
+                removed: before_packets[shared..].iter().filter(|p| !p.is_empty()).count(),
+            });
+        }
+        diffs
+    }
+
+    /// Order-sensitive equality: the same containers at the same indices.
+    /// Unlike `==`, swapping two different containers makes the states unequal.
+    pub fn eq_exact(&self, other: &GameState) -> bool {
+        self.fluid_containers == other.fluid_containers
+    }
 }
 
+/// Two states are equal when they hold the same containers in any order, since container order
+/// never affects which moves are possible. Use `GameState::eq_exact` when order matters.
 impl PartialEq for GameState {
     fn eq(&self, other: &Self) -> bool {
         self.get_sorted_containers() == other.get_sorted_containers()
@@ -523,6 +1360,13 @@ impl PartialEq for GameState {
 
 impl Eq for GameState {}
 
+/// Hashes the sorted containers, the same canonical form `eq` compares, so reordered boards land in the same bucket.
+impl std::hash::Hash for GameState {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.get_sorted_containers().hash(state);
+    }
+}
+
 // Controls / Button
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -534,18 +1378,36 @@ pub enum ControlAction {
     ReversePour(usize, usize, usize),
     Undo,
     Redo,
+    UndoAll,
+    RedoAll,
     Reset,
     ToggleEditor,
     CopyState,
+    Hint,
+    AdvanceColor(usize),
+    SolveFromHere,
+    StepForward,
+    StepBack,
+    /// Handed to the embedder's callback, see `GameEngine::set_custom_action_handler`.
+    Custom(u32),
     // Editor actions
     PasteState,
     AddColor(usize, usize),
+    FillContainer(usize, usize),
     RemoveColor(usize),
     AddContainer,
     RemoveContainer,
     ExpandContainer,
     ShrinkContainer,
+    ClearContainer,
+    TrimEmptyContainers(usize),
+    PurgeColor(usize),
+    MoveContainer(usize, usize),
+    /// Swaps the selected container with the next one clicked.
+    BeginSwap,
+    SwapContainers(usize, usize),
     ShuffleState,
+    NewScramble,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -576,15 +1438,34 @@ impl Button {
         self.color
     }
 
+    /// Buttons that lean on the solver, hidden in hard mode.
+    pub fn solver_assisted(&self) -> bool {
+        matches!(
+            self.action,
+            ControlAction::Hint
+                | ControlAction::AdvanceColor(_)
+                | ControlAction::SolveFromHere
+                | ControlAction::StepForward
+                | ControlAction::StepBack
+        )
+    }
+
     pub fn editor_mode(&self) -> bool {
         matches!(
             self.action,
             ControlAction::AddColor(_, _)
+                | ControlAction::FillContainer(_, _)
                 | ControlAction::RemoveColor(_)
                 | ControlAction::AddContainer
                 | ControlAction::RemoveContainer
                 | ControlAction::ExpandContainer
                 | ControlAction::ShrinkContainer
+                | ControlAction::ClearContainer
+                | ControlAction::TrimEmptyContainers(_)
+                | ControlAction::PurgeColor(_)
+                | ControlAction::MoveContainer(_, _)
+                | ControlAction::BeginSwap
+                | ControlAction::SwapContainers(_, _)
                 | ControlAction::PasteState
         )
     }
@@ -597,18 +1478,27 @@ pub enum HitItem {
     Button { function: ControlAction },
     Container { index: usize },
     Swatch { index: usize },
-    #[allow(dead_code)]
     PacketInContainer {
         container_index: usize,
         packet_index: usize,
     },
 }
 
+impl HitItem {
+    /// The container this item belongs to, if any.
+    pub fn container_index(&self) -> Option<usize> {
+        match self {
+            HitItem::Container { index } => Some(*index),
+            HitItem::PacketInContainer { container_index, .. } => Some(*container_index),
+            HitItem::Button { .. } | HitItem::Swatch { .. } => None,
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct HitRecord {
     pub rect: Rect,
     pub item: HitItem,
-    #[allow(dead_code)]
     pub order: usize,
 }
 
@@ -630,6 +1520,14 @@ impl HitTestRegistry {
         self.items.push(HitRecord { rect, item, order });
     }
 
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
     /// Returns the topmost item under the point (highest draw order).
     pub fn hit_test(&self, x: f32, y: f32) -> Option<&HitRecord> {
         self.items
@@ -639,7 +1537,6 @@ impl HitTestRegistry {
     }
 
     /// Returns all items under the point, ordered topmost-first.
-    #[allow(dead_code)]
     pub fn hit_test_all(&self, x: f32, y: f32) -> Vec<&HitRecord> {
         self.items
             .iter()
@@ -648,3 +1545,209 @@ impl HitTestRegistry {
             .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn huge_capacity_directives_are_clamped() {
+        let state = GameState::new_from_repr("cap=99999999999999\nAB");
+        assert_eq!(state.container(0).map(|c| c.get_capacity()), Some(MAX_DIRECTIVE_CAPACITY));
+        let state = GameState::new_from_repr("cap=999999999999999999999999 AB");
+        assert_eq!(state.container(0).map(|c| c.get_capacity()), Some(MAX_DIRECTIVE_CAPACITY));
+        let state = GameState::new_from_repr("cap=6 AB");
+        assert_eq!(state.container(0).map(|c| c.get_capacity()), Some(6));
+    }
+
+    #[test]
+    fn color_names_past_the_biggest_id_paste_as_empty() {
+        // Fourteen letters is far past u32::MAX, packing it used to overflow
+        let state = GameState::new_from_repr("EAAAAAAAAAAAAA,");
+        assert_eq!(state.container(0).map(|c| c.is_empty()), Some(true));
+        let _ = crate::packed::PackedState::from_state(&state);
+        let _ = state.to_bytes();
+        let state = GameState::new_from_repr("A,ZZZZZZ");
+        assert_eq!(state.get_available_colors(), vec![0, 321272405]);
+    }
+
+    // Source, destination, both after pouring one into the other, and how deep the destination's top run ends up
+    const KNOWN_POURS: &[(&str, &str, &str, &str, usize)] = &[
+        ("BAAA", "cap=6 AA", "B...", "cap=6 AAAAA", 5),
+        // Only as much as fits goes, the rest of the run stays behind
+        ("AAAAA", "cap=3 A", "AAA..", "AAA", 3),
+        ("cap=6 BAAA", "A.", "BAA...", "AA", 2),
+        ("AA", "......", "..", "AA....", 2),
+        ("cap=4 #AA", "cap=4 A", "#...", "AAA.", 3),
+        // Wildcards go along with the run and count towards it
+        ("A*", "cap=5 AA", "..", "AA*A.", 4),
+        // Nothing moves onto another color or into a full container
+        ("AAA", "cap=6 BB", "AAA", "cap=6 BB", 2),
+        ("cap=6 AA", "AAA", "cap=6 AA", "AAA", 3),
+    ];
+
+    fn pour(from: &str, to: &str) -> GameState {
+        let mut state = GameState::new_from_repr(&format!("{}\n{}", from, to));
+        state.apply_move(&MoveAction {
+            from_container: 0,
+            to_container: 1,
+            amount: 0,
+        });
+        state
+    }
+
+    #[test]
+    fn pouring_a_run_onto_the_same_color_in_a_bigger_container_makes_one_run() {
+        let mut from = FluidContainer::new_from_repr("AAA");
+        let mut to = FluidContainer::new_from_repr("AA....");
+        assert_eq!(from.get_pourable_amount(&to), 3);
+        assert!(from.pour_into(&mut to, PourRule::Standard));
+        assert!(from.is_empty());
+        assert_eq!(to.get_top_fluid_depth(), 5);
+        assert_eq!(to.get_filled_amount(), 5);
+        assert_eq!(to.get_capacity(), 6);
+    }
+
+    #[test]
+    fn known_pours_across_capacities() {
+        for &(from, to, expected_from, expected_to, expected_run) in KNOWN_POURS {
+            let state = pour(from, to);
+            let expected = GameState::new_from_repr(&format!("{}\n{}", expected_from, expected_to));
+            assert!(state.eq_exact(&expected), "pouring {:?} into {:?} gave\n{}", from, to, state.get_text_representation());
+            let run = state.container(1).map_or(0, |container| container.get_top_fluid_depth());
+            assert_eq!(run, expected_run, "pouring {:?} into {:?}", from, to);
+        }
+    }
+
+    #[test]
+    fn reverse_pour_takes_a_whole_one_color_container() {
+        let full = FluidContainer::new_from_repr("AAAA");
+        assert_eq!(full.get_reverse_pourable_amount(&FluidContainer::new_from_repr("....")), 4);
+    }
+
+    #[test]
+    fn reverse_pour_takes_a_single_packet() {
+        let single = FluidContainer::new_from_repr("A...");
+        assert_eq!(single.get_reverse_pourable_amount(&FluidContainer::new_from_repr("....")), 1);
+    }
+
+    #[test]
+    fn reverse_pour_leaves_one_of_the_run_behind() {
+        let stacked = FluidContainer::new_from_repr("BAAA");
+        assert_eq!(stacked.get_reverse_pourable_amount(&FluidContainer::new_from_repr("....")), 2);
+    }
+
+    #[test]
+    fn reverse_pour_is_limited_by_the_destination_space() {
+        let full = FluidContainer::new_from_repr("AAAA");
+        assert_eq!(full.get_reverse_pourable_amount(&FluidContainer::new_from_repr("BB..")), 2);
+        assert_eq!(full.get_reverse_pourable_amount(&FluidContainer::new_from_repr("BBB.")), 1);
+        assert_eq!(full.get_reverse_pourable_amount(&FluidContainer::new_from_repr("BBBB")), 0);
+    }
+
+    #[test]
+    fn reverse_pour_never_lands_on_a_stone() {
+        let source = FluidContainer::new_from_repr("AA..");
+        assert_eq!(source.get_reverse_pourable_amount(&FluidContainer::new_from_repr("#...")), 0);
+    }
+
+    #[test]
+    fn reverse_move_asking_for_too_much_moves_what_it_can() {
+        let mut state = GameState::new_from_repr("BAAA\n....");
+        let moved = state.apply_reverse_move(&MoveAction {
+            from_container: 0,
+            to_container: 1,
+            amount: 5,
+        });
+        assert_eq!(moved, 2);
+        assert!(state.eq_exact(&GameState::new_from_repr("BA..\nAA..")));
+    }
+
+    #[test]
+    fn reverse_move_that_cant_move_anything_returns_zero() {
+        let mut state = GameState::new_from_repr("AAAA\nBBBB");
+        let action = MoveAction {
+            from_container: 0,
+            to_container: 1,
+            amount: 1,
+        };
+        assert_eq!(state.apply_reverse_move(&action), 0);
+        assert!(state.eq_exact(&GameState::new_from_repr("AAAA\nBBBB")));
+        let out_of_range = MoveAction { to_container: 7, ..action };
+        assert_eq!(state.apply_reverse_move(&out_of_range), 0);
+    }
+
+    fn empty_board(layout: &[(usize, usize)]) -> GameState {
+        let lines: Vec<String> = layout
+            .iter()
+            .flat_map(|&(count, capacity)| std::iter::repeat_n(".".repeat(capacity), count))
+            .collect();
+        GameState::new_from_repr(&lines.join("\n"))
+    }
+
+    #[test]
+    fn max_colors_of_classic_layouts() {
+        // Containers as (how many, capacity), and the colors the layout is known to be played with
+        let layouts: &[(&[(usize, usize)], usize)] = &[
+            (&[(14, 4)], 12),
+            (&[(11, 4)], 9),
+            (&[(5, 4)], 3),
+            (&[(9, 3)], 7),
+            (&[(4, 5)], 2),
+            // Two half size containers add up to one more container of buffer
+            (&[(12, 4), (2, 2)], 11),
+            (&[(2, 5)], 0),
+            (&[], 0),
+        ];
+        for &(layout, expected) in layouts {
+            assert_eq!(empty_board(layout).max_colors_allowed(), expected, "{:?}", layout);
+        }
+    }
+
+    fn runs(repr: &str) -> Vec<(usize, usize)> {
+        FluidContainer::new_from_repr(repr).iter_runs().collect()
+    }
+
+    #[test]
+    fn runs_of_an_alternating_container() {
+        assert_eq!(runs("ABAB"), vec![(0, 1), (1, 1), (0, 1), (1, 1)]);
+    }
+
+    #[test]
+    fn runs_of_a_one_color_container() {
+        assert_eq!(runs("CCCC"), vec![(2, 4)]);
+        assert_eq!(runs("CC.."), vec![(2, 2)]);
+        assert_eq!(FluidContainer::new_from_repr("CCCC").get_longest_run(), 4);
+    }
+
+    #[test]
+    fn runs_skip_empty_containers_and_split_at_stones() {
+        assert_eq!(runs("...."), vec![]);
+        assert_eq!(runs("AA#AB"), vec![(0, 2), (0, 1), (1, 1)]);
+    }
+
+    fn hash_of(state: &GameState) -> u64 {
+        use std::hash::{DefaultHasher, Hash, Hasher};
+        let mut hasher = DefaultHasher::new();
+        state.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn reordered_boards_hash_the_same() {
+        let state = GameState::new_from_repr("AB..\nBA..\n....\nAABB");
+        let reordered = GameState::new_from_repr("AABB\n....\nBA..\nAB..");
+        assert_eq!(state, reordered);
+        assert!(!state.eq_exact(&reordered));
+        assert_eq!(hash_of(&state), hash_of(&reordered));
+        let different = GameState::new_from_repr("AB..\nAB..\n....\nAABB");
+        assert_ne!(state, different);
+    }
+
+    #[test]
+    fn remove_color_takes_hidden_packets_too() {
+        let mut container = FluidContainer::new_from_repr("?A?BA.");
+        assert_eq!(container.remove_color(0), 2);
+        assert_eq!(container, FluidContainer::new_from_repr("?B..."));
+    }
+}
@@ -0,0 +1,94 @@
+use crate::model::*;
+use rayon::prelude::*;
+
+/// Something wrong with one board in a pack.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationIssue {
+    /// No containers, or no liquid in any of them.
+    Empty,
+    AlreadySolved,
+    /// The colors don't fit the containers, or no sequence of pours gets from the board to a solved one.
+    Unsolvable,
+}
+
+/// A list of boards played one after another.
+/// As text, boards are written one after another in the usual format with a blank line between them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LevelPack {
+    pub levels: Vec<GameState>,
+}
+
+impl LevelPack {
+    pub fn new(levels: Vec<GameState>) -> Self {
+        Self { levels }
+    }
+
+    pub fn from_text(text: &str) -> Self {
+        let mut levels = vec![];
+        let mut current = String::new();
+        for line in text.lines().chain(std::iter::once("")) {
+            if !line.trim().is_empty() {
+                current.push_str(line);
+                current.push('\n');
+            } else if !current.is_empty() {
+                levels.push(GameState::new_from_repr(&current));
+                current.clear();
+            }
+        }
+        Self { levels }
+    }
+
+    pub fn get_text_representation(&self) -> String {
+        self.levels
+            .iter()
+            .map(|level| level.get_text_representation())
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    /// Checks every level and returns the index and issues of each one that has any.
+    /// Every board that isn't empty or already solved gets searched for a solution, which can take a while.
+    pub fn validate_all(&self) -> Vec<(usize, Vec<ValidationIssue>)> {
+        self.levels
+            .par_iter()
+            .enumerate()
+            .map(|(index, level)| (index, Self::validate(level)))
+            .filter(|(_, issues)| !issues.is_empty())
+            .collect()
+    }
+
+    pub fn validate(level: &GameState) -> Vec<ValidationIssue> {
+        if level.get_available_colors().is_empty() {
+            return vec![ValidationIssue::Empty];
+        }
+        if level.is_solved() {
+            return vec![ValidationIssue::AlreadySolved];
+        }
+        // Only solvability counts, `max_colors_allowed` is a layout guideline and tighter layouts can still be solved.
+        // The cheap fit check goes first, then an actual search, unless the board is one the solver won't look at.
+        let searchable = level.pour_rule.allows_solving() && level.is_fully_revealed();
+        if !level.is_solvable() || (searchable && level.min_moves().is_none()) {
+            return vec![ValidationIssue::Unsolvable];
+        }
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_agrees_with_is_solvable() {
+        // A color bigger than any one container is still solvable
+        let level = GameState::new_from_repr("AAAB\nA\nABBB\n....");
+        assert!(level.is_solvable());
+        assert_eq!(LevelPack::validate(&level), vec![]);
+        let level = GameState::new_from_repr("AB\nAB\nAB");
+        assert_eq!(LevelPack::validate(&level), vec![ValidationIssue::Unsolvable]);
+        // The colors fit, but there's no room to make a single move
+        let level = GameState::new_from_repr("AAB\nBBA");
+        assert!(level.is_solvable());
+        assert_eq!(LevelPack::validate(&level), vec![ValidationIssue::Unsolvable]);
+    }
+}
@@ -0,0 +1,108 @@
+use crate::model::*;
+
+// Values below this take one byte, anything bigger is this marker followed by the full usize.
+const WIDE_MARKER: u8 = u8::MAX;
+
+/// The number a packet is stored as, see `PackedState`. The binary snapshot uses the same numbering.
+pub(crate) fn packet_code(packet: &FluidPacket) -> usize {
+    match packet {
+        FluidPacket::Empty => 0,
+        FluidPacket::Blocked => 1,
+        FluidPacket::Wildcard => 2,
+        FluidPacket::Fluid { color_id, revealed } => 2 * color_id + 3 + usize::from(!revealed),
+    }
+}
+
+pub(crate) fn packet_from_code(code: usize) -> FluidPacket {
+    match code {
+        0 => FluidPacket::Empty,
+        1 => FluidPacket::Blocked,
+        2 => FluidPacket::Wildcard,
+        code if (code - 3) % 2 == 0 => FluidPacket::new((code - 3) / 2),
+        code => FluidPacket::new_hidden((code - 3) / 2),
+    }
+}
+
+/// A board squashed into a single byte buffer, for the solver's hot paths.
+/// Each container is its capacity followed by one value per packet (0 for empty, 1 for blocked, 2 for a wildcard,
+/// then 2 * color id + 3 for a revealed packet and one more than that for a hidden one),
+/// so cloning, comparing and hashing touch one small allocation instead of a vec per container.
+/// The pour rule isn't stored.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PackedState {
+    bytes: Box<[u8]>,
+}
+
+impl PackedState {
+    /// Packs the containers in the order they're in.
+    pub fn from_state(state: &GameState) -> PackedState {
+        let mut bytes = vec![];
+        for container in &state.fluid_containers {
+            Self::push_container(container, &mut bytes);
+        }
+        PackedState { bytes: bytes.into_boxed_slice() }
+    }
+
+    /// Packs the containers sorted, so two boards that only differ in container order pack the same.
+    /// This is the packed version of `GameState::get_sorted_containers`.
+    pub fn canonical(state: &GameState) -> PackedState {
+        let mut buffer = vec![];
+        let mut spans = Vec::with_capacity(state.container_count());
+        for container in &state.fluid_containers {
+            let start = buffer.len();
+            Self::push_container(container, &mut buffer);
+            spans.push(start..buffer.len());
+        }
+        spans.sort_by(|a, b| buffer[a.clone()].cmp(&buffer[b.clone()]));
+        let mut bytes = Vec::with_capacity(buffer.len());
+        for span in spans {
+            bytes.extend_from_slice(&buffer[span]);
+        }
+        PackedState { bytes: bytes.into_boxed_slice() }
+    }
+
+    /// Unpacks into a board with the default pour rule.
+    pub fn to_state(&self) -> GameState {
+        let mut fluid_containers = vec![];
+        let mut position = 0;
+        while position < self.bytes.len() {
+            let capacity = self.read_value(&mut position);
+            let packets = (0..capacity)
+                .map(|_| packet_from_code(self.read_value(&mut position)))
+                .collect();
+            fluid_containers.push(FluidContainer::from_packets(packets));
+        }
+        GameState {
+            fluid_containers,
+            pour_rule: PourRule::default(),
+        }
+    }
+
+    fn push_container(container: &FluidContainer, bytes: &mut Vec<u8>) {
+        Self::push_value(container.get_capacity(), bytes);
+        for packet in container.get_packets() {
+            Self::push_value(packet_code(packet), bytes);
+        }
+    }
+
+    fn push_value(value: usize, bytes: &mut Vec<u8>) {
+        if value < WIDE_MARKER as usize {
+            bytes.push(value as u8);
+        } else {
+            bytes.push(WIDE_MARKER);
+            bytes.extend_from_slice(&(value as u64).to_le_bytes());
+        }
+    }
+
+    fn read_value(&self, position: &mut usize) -> usize {
+        let byte = self.bytes[*position];
+        *position += 1;
+        if byte != WIDE_MARKER {
+            return byte as usize;
+        }
+        let mut wide = [0; 8];
+        wide.copy_from_slice(&self.bytes[*position..*position + 8]);
+        *position += 8;
+        u64::from_le_bytes(wide) as usize
+    }
+}
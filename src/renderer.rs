@@ -18,6 +18,12 @@ pub struct CachedTextSizer {
     unscaled_size_cache: Mutex<HashMap<String, (f32, f32)>>,
 }
 
+impl Default for CachedTextSizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl CachedTextSizer {
     pub fn new() -> Self {
         Self {
@@ -77,32 +83,211 @@ impl CachedTextSizer {
         (optimal_size, offset_x, offset_y)
     }
 }
+
+/// Spacing used when laying out a frame. Sizes are in pixels unless marked as a ratio.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LayoutConfig {
+    pub outer_margin: f32,
+    pub gamefield_padding: f32,
+    pub text_padding: f32,
+    pub container_padding_horizontal: f32,
+    pub container_padding_vertical: f32,
+    /// Ratio of the container width left blank on each side of the divider lines.
+    pub container_line_padding: f32,
+    pub button_padding_horizontal: f32,
+    pub swatch_padding_horizontal: f32,
+    /// Ratio of the screen height taken by the button row.
+    pub button_height: f32,
+    /// Ratio of the screen height taken by the swatch row.
+    pub swatch_height: f32,
+    /// Ratio of the screen width given to the goal panel, when it's shown.
+    pub target_panel_width: f32,
+    /// Below this packet height only the top of each color run gets a letter, sized to the whole run. 0 labels every packet.
+    pub min_labeled_packet_height: f32,
+}
+
+impl Default for LayoutConfig {
+    fn default() -> Self {
+        Self {
+            outer_margin: 10.0,
+            gamefield_padding: 10.0,
+            text_padding: 10.0,
+            container_padding_horizontal: 10.0,
+            container_padding_vertical: 10.0,
+            container_line_padding: 0.1,
+            button_padding_horizontal: 10.0,
+            swatch_padding_horizontal: 5.0,
+            button_height: 0.1,
+            swatch_height: 0.1,
+            target_panel_width: 0.2,
+            min_labeled_packet_height: 24.0,
+        }
+    }
+}
+
+/// Colors for everything that isn't liquid or a button.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Theme {
+    pub background: Color,
+    pub text: Color,
+    /// Less important text like container labels.
+    pub secondary_text: Color,
+    /// Container outlines and the dividers between packets.
+    pub line: Color,
+    pub selection: Color,
+    pub empty_packet: EmptyPacketStyle,
+}
+
+/// How the empty spots in a container are drawn, all in the theme's line color.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EmptyPacketStyle {
+    #[default]
+    Outline,
+    /// A see-through fill with no border, easier to pick out on dark backgrounds.
+    FaintFill,
+    /// Nothing at all, the container outline still shows how much room there is.
+    Transparent,
+    DottedOutline,
+}
+
+impl EmptyPacketStyle {
+    /// The style after this one, wrapping around, for cycling through them with a key.
+    pub fn next(self) -> Self {
+        match self {
+            EmptyPacketStyle::Outline => EmptyPacketStyle::FaintFill,
+            EmptyPacketStyle::FaintFill => EmptyPacketStyle::Transparent,
+            EmptyPacketStyle::Transparent => EmptyPacketStyle::DottedOutline,
+            EmptyPacketStyle::DottedOutline => EmptyPacketStyle::Outline,
+        }
+    }
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        Self {
+            background: BLACK,
+            text: WHITE,
+            secondary_text: LIGHTGRAY,
+            line: DARKGRAY,
+            selection: WHITE,
+            empty_packet: EmptyPacketStyle::Outline,
+        }
+    }
+
+    pub fn light() -> Self {
+        Self {
+            background: WHITE,
+            text: BLACK,
+            secondary_text: DARKGRAY,
+            line: GRAY,
+            selection: BLACK,
+            empty_packet: EmptyPacketStyle::Outline,
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+/// Where the parts of the last frame went, in screen pixels, for drawing extra UI around the game.
+/// Areas that weren't drawn (no swatches in hard mode, no goal panel) have zero width or height.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LayoutInfo {
+    pub button_area: Rect,
+    pub container_area: Rect,
+    pub target_panel: Rect,
+    pub swatch_area: Rect,
+}
+
+/// Liquid on its way from one container to another. The board has already changed,
+/// this only delays showing the poured packets in their new container.
+#[derive(Clone, Copy, Debug)]
+struct PourAnimation {
+    from: usize,
+    to: usize,
+    amount: usize,
+    packet: FluidPacket,
+    // Filled amounts right after the pour. If either container changes before the animation ends it's dropped.
+    from_filled: usize,
+    to_filled: usize,
+    start: f64,
+    duration: f64,
+}
+
 pub struct Renderer {
     cached_text_sizer: CachedTextSizer,
+    layout: LayoutConfig,
+    theme: Theme,
+    show_dividers: bool,
+    show_perf: bool,
+    status_text: Option<String>,
+    // Packets drawn this frame, for the perf overlay
+    packets_drawn: usize,
     hit_test: HitTestRegistry,
     draw_order: usize,
+    flash: Option<(usize, f64)>,
+    highlighted: Vec<usize>,
+    highlighted_packets: Vec<(usize, usize)>,
+    completed_colors: Vec<usize>,
+    animation_speed: f32,
+    pour_animation: Option<PourAnimation>,
+    // Source, destination and when the arrow for the last pour disappears
+    move_arrow: Option<(usize, usize, f64)>,
+    // Where each container was drawn this frame, by index
+    container_rects: Vec<Rect>,
+    target: Vec<FluidContainer>,
+    last_layout: LayoutInfo,
+    // The last full frame, shown again while nothing changes
+    frame_cache: Option<RenderTarget>,
+    // Whether the last frame had animations in it, so the frame after they end is drawn too
+    moved_last_frame: bool,
     x: f32,
     y: f32,
     width: f32,
     height: f32,
 }
 const SELECTION_BORDER: f32 = 4.0;
-const TEXT_PADDING: f32 = 10.0;
-const CONTAINER_PADDING_HORIZONTAL: f32 = 10.0;
-const CONTAINER_PADDING_VERTICAL: f32 = 10.0;
-const CONTAINER_LINE_PADDING: f32 = 0.1;
-const BUTTON_PADDING_HORIZONTAL: f32 = 10.0;
-const BUTTON_HEIGHT: f32 = 0.1;
-const SWATCH_HEIGHT: f32 = 0.1;
-const GAMEFIELD_PADDING: f32 = 10.0;
-const OUTER_MARGIN: f32 = 10.0;
+const FLASH_DURATION: f64 = 0.3;
+// How long a pour takes at speed 1
+const POUR_DURATION: f64 = 0.25;
+const MOVE_ARROW_DURATION: f64 = 1.0;
+const EMPTY_FILL_ALPHA: f32 = 0.3;
+// Length of each dash and of the gap after it
+const DOT_LENGTH: f32 = 4.0;
+
+impl Default for Renderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl Renderer {
     pub fn new() -> Self {
         Self {
             cached_text_sizer: CachedTextSizer::new(),
+            layout: LayoutConfig::default(),
+            theme: Theme::default(),
+            show_dividers: true,
+            show_perf: false,
+            status_text: None,
+            packets_drawn: 0,
             hit_test: HitTestRegistry::new(),
             draw_order: 0,
+            flash: None,
+            highlighted: Vec::new(),
+            highlighted_packets: Vec::new(),
+            completed_colors: Vec::new(),
+            animation_speed: 1.0,
+            pour_animation: None,
+            move_arrow: None,
+            container_rects: Vec::new(),
+            target: Vec::new(),
+            last_layout: LayoutInfo::default(),
+            frame_cache: None,
+            moved_last_frame: false,
             x: 0.0,
             y: 0.0,
             width: 800.0,
@@ -120,14 +305,211 @@ impl Renderer {
         &self.hit_test
     }
 
+    pub fn get_layout(&self) -> LayoutConfig {
+        self.layout
+    }
+
+    /// Takes effect from the next viewport update.
+    pub fn set_layout(&mut self, layout: LayoutConfig) {
+        self.layout = layout;
+    }
+
+    /// The areas computed by the last `render_game`, all zero before the first frame.
+    pub fn last_layout(&self) -> LayoutInfo {
+        self.last_layout
+    }
+
+    pub fn get_theme(&self) -> Theme {
+        self.theme
+    }
+
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+    }
+
+    /// Turning the lines between packets off draws each run as a solid column.
+    pub fn set_show_dividers(&mut self, show_dividers: bool) {
+        self.show_dividers = show_dividers;
+    }
+
+    pub fn is_showing_dividers(&self) -> bool {
+        self.show_dividers
+    }
+
+    /// A corner readout of frame time, hit-test records and packets drawn, for finding out why a big board is slow.
+    pub fn set_show_perf(&mut self, show_perf: bool) {
+        self.show_perf = show_perf;
+    }
+
+    pub fn is_showing_perf(&self) -> bool {
+        self.show_perf
+    }
+
+    /// Briefly outlines a container in red, used to reject a pour without changing the selection.
+    pub fn flash_container(&mut self, container_index: usize) {
+        self.flash = Some((container_index, get_time() + FLASH_DURATION));
+    }
+
+    fn is_flashing(&self, container_index: usize) -> bool {
+        matches!(self.flash, Some((index, until)) if index == container_index && get_time() < until)
+    }
+
+    /// How fast pours play out: 1.0 is normal, 2.0 twice as fast, and 0.0 skips the animation entirely.
+    pub fn set_animation_speed(&mut self, speed: f32) {
+        self.animation_speed = speed.max(0.0);
+        if self.animation_speed == 0.0 {
+            self.pour_animation = None;
+        }
+    }
+
+    pub fn get_animation_speed(&self) -> f32 {
+        self.animation_speed
+    }
+
+    /// Stretches or shrinks a duration by the animation speed. Instant speed makes everything take no time.
+    pub fn scale_duration(&self, seconds: f64) -> f64 {
+        if self.animation_speed == 0.0 {
+            0.0
+        } else {
+            seconds / self.animation_speed as f64
+        }
+    }
+
+    /// Shows `amount` packets flying from one container to the other. Call right after the pour was applied.
+    /// A new pour replaces one still in flight, which just lands early.
+    /// Clicks keep working while it plays, since the containers themselves never move.
+    pub fn animate_pour(&mut self, from: &FluidContainer, to: &FluidContainer, from_index: usize, to_index: usize, amount: usize) {
+        if self.animation_speed == 0.0 || amount == 0 {
+            self.pour_animation = None;
+            return;
+        }
+        self.pour_animation = Some(PourAnimation {
+            from: from_index,
+            to: to_index,
+            amount,
+            packet: to.get_top_fluid(),
+            from_filled: from.get_filled_amount(),
+            to_filled: to.get_filled_amount(),
+            start: get_time(),
+            duration: self.scale_duration(POUR_DURATION),
+        });
+    }
+
+    /// Points a faint arrow from one container to another for a moment, showing what the last pour did.
+    /// Replaces any arrow still showing.
+    pub fn show_move_arrow(&mut self, from: usize, to: usize) {
+        self.move_arrow = Some((from, to, get_time() + MOVE_ARROW_DURATION));
+    }
+
+    pub fn is_animating(&self) -> bool {
+        self.pour_animation.is_some()
+    }
+
+    /// How many packets at the top of this container are still in flight and shouldn't be drawn yet.
+    fn packets_in_flight(&self, container_index: usize) -> usize {
+        match self.pour_animation {
+            Some(animation) if animation.to == container_index => animation.amount,
+            _ => 0,
+        }
+    }
+
+    /// A line of text drawn in the top left corner over the board, or None for nothing.
+    pub fn set_status_text(&mut self, status_text: Option<String>) {
+        self.status_text = status_text;
+    }
+
+    /// The solved arrangement to show next to the board, or an empty vec to hide the goal panel.
+    pub fn set_target(&mut self, target: Vec<FluidContainer>) {
+        self.target = target;
+    }
+
+    /// Outlines these containers in yellow until replaced, e.g. with the result of `GameState::diff`.
+    pub fn set_highlighted_containers(&mut self, container_indices: Vec<usize>) {
+        self.highlighted = container_indices;
+    }
+
+    /// Outlines single packets, as `(container_index, packet_index)`, until replaced.
+    pub fn set_highlighted_packets(&mut self, packets: Vec<(usize, usize)>) {
+        self.highlighted_packets = packets;
+    }
+
+    pub fn get_highlighted_packets(&self) -> &[(usize, usize)] {
+        &self.highlighted_packets
+    }
+
+    /// Color ids whose swatches get a checkmark, see `GameState::completed_colors`.
+    pub fn set_completed_colors(&mut self, color_ids: Vec<usize>) {
+        self.completed_colors = color_ids;
+    }
+
+    /// Whether anything on screen changes by itself over time: a pour, the move arrow, a flash or the perf overlay.
+    pub fn has_moving_parts(&self) -> bool {
+        let now = get_time();
+        self.pour_animation.is_some()
+            || self.move_arrow.is_some_and(|(_, _, until)| now < until)
+            || self.flash.is_some_and(|(_, until)| now < until)
+            || self.show_perf
+    }
+
+    /// Like `has_moving_parts`, but also true for one frame after the last of them stopped, to draw it gone.
+    pub fn take_moving_parts(&mut self) -> bool {
+        let moving = self.has_moving_parts();
+        let moved = self.moved_last_frame;
+        self.moved_last_frame = moving;
+        moving || moved
+    }
+
+    /// Sends drawing into the frame cache until `end_cached_frame`, remaking it if the viewport changed size.
+    pub fn begin_cached_frame(&mut self) {
+        let dpi_scale = screen_dpi_scale();
+        let (width, height) = ((self.width * dpi_scale).max(1.0) as u32, (self.height * dpi_scale).max(1.0) as u32);
+        let target = match &self.frame_cache {
+            Some(target) if target.texture.width() as u32 == width && target.texture.height() as u32 == height => target.clone(),
+            _ => {
+                let target = render_target(width, height);
+                self.frame_cache = Some(target.clone());
+                target
+            }
+        };
+        let mut camera = Camera2D::from_display_rect(Rect::new(self.x, self.y, self.width, self.height));
+        camera.render_target = Some(target);
+        set_camera(&camera);
+    }
+
+    /// Goes back to drawing on the screen and puts the frame just drawn there.
+    pub fn end_cached_frame(&mut self) {
+        set_default_camera();
+        self.draw_cached_frame();
+    }
+
+    /// Shows the last cached frame again. False if there isn't one yet.
+    pub fn draw_cached_frame(&self) -> bool {
+        let Some(target) = &self.frame_cache else {
+            return false;
+        };
+        draw_texture_ex(
+            &target.texture,
+            self.x,
+            self.y,
+            WHITE,
+            DrawTextureParams {
+                dest_size: Some(vec2(self.width, self.height)),
+                // Render targets come out upside down
+                flip_y: true,
+                ..Default::default()
+            },
+        );
+        true
+    }
+
     pub fn set_viewport(&mut self, x: f32, y: f32, width: f32, height: f32) -> bool {
         if self.x == x && self.y == y && self.width == width && self.height == height {
             return false;
         }
-        self.x = x + OUTER_MARGIN;
-        self.y = y + OUTER_MARGIN;
-        self.width = width - OUTER_MARGIN * 2.0;
-        self.height = height - OUTER_MARGIN * 2.0;
+        self.x = x + self.layout.outer_margin;
+        self.y = y + self.layout.outer_margin;
+        self.width = width - self.layout.outer_margin * 2.0;
+        self.height = height - self.layout.outer_margin * 2.0;
         true
     }
 
@@ -148,41 +530,148 @@ impl Renderer {
         // New frame: reset hit-test registry and draw order.
         self.hit_test.clear();
         self.draw_order = 0;
+        self.packets_drawn = 0;
+        self.container_rects.clear();
+        if let Some(animation) = self.pour_animation {
+            let finished = get_time() >= animation.start + animation.duration;
+            // Undo, paste and the like change the board under the animation, at which point it no longer fits
+            let still_fits = containers.get(animation.from).is_some_and(|c| c.get_filled_amount() == animation.from_filled)
+                && containers.get(animation.to).is_some_and(|c| c.get_filled_amount() == animation.to_filled);
+            if finished || !still_fits {
+                self.pour_animation = None;
+            }
+        }
 
-        clear_background(BLACK);
-        let button_area_height = self.height * BUTTON_HEIGHT;
+        clear_background(self.theme.background);
+        let button_area_height = self.height * self.layout.button_height;
         let swatch_area_height = if !swatches.is_empty() {
-            self.height * SWATCH_HEIGHT
+            self.height * self.layout.swatch_height
         } else {
             0.0
         };
-        let container_area_height = self.height - button_area_height - swatch_area_height - 2.0 * GAMEFIELD_PADDING;
-        self.render_button_lineup(
-            buttons,
-            selected_button,
-            Rect::new(self.x, self.y, self.width, button_area_height),
-        );
-        self.render_container_grid(
-            containers,
-            selected_container,
-            6,
-            Rect::new(
+        let container_area_height = self.height - button_area_height - swatch_area_height - 2.0 * self.layout.gamefield_padding;
+        // Without a target the goal panel is hidden and the board gets the full width
+        let target_width = if !self.target.is_empty() {
+            self.width * self.layout.target_panel_width
+        } else {
+            0.0
+        };
+        let board_width = if !self.target.is_empty() {
+            self.width - target_width - self.layout.gamefield_padding
+        } else {
+            self.width
+        };
+        let layout = LayoutInfo {
+            button_area: Rect::new(self.x, self.y, self.width, button_area_height),
+            container_area: Rect::new(
                 self.x,
-                self.y + button_area_height + GAMEFIELD_PADDING,
-                self.width,
+                self.y + button_area_height + self.layout.gamefield_padding,
+                board_width,
                 container_area_height,
             ),
-        );
-        self.render_color_swatches(
-            swatches,
-            selected_swatch,
-            Rect::new(
+            target_panel: Rect::new(
+                self.x + self.width - target_width,
+                self.y + button_area_height + self.layout.gamefield_padding,
+                target_width,
+                container_area_height,
+            ),
+            swatch_area: Rect::new(
                 self.x,
-                self.y + button_area_height + container_area_height + 2.0 * GAMEFIELD_PADDING,
+                self.y + button_area_height + container_area_height + 2.0 * self.layout.gamefield_padding,
                 self.width,
                 swatch_area_height,
             ),
+        };
+        self.last_layout = layout;
+        self.render_button_lineup(buttons, selected_button, layout.button_area);
+        self.render_container_grid(containers, selected_container, 6, layout.container_area);
+        self.render_pour_animation(containers);
+        self.render_move_arrow();
+        self.render_target_panel(&self.target, layout.target_panel);
+        self.render_color_swatches(swatches, selected_swatch, layout.swatch_area);
+        if let Some(text) = &self.status_text {
+            self.render_status_text(text, layout.container_area);
+        }
+        if self.show_perf {
+            self.render_perf_overlay();
+        }
+    }
+
+    fn render_pour_animation(&mut self, containers: &[&FluidContainer]) {
+        let Some(animation) = self.pour_animation else {
+            return;
+        };
+        let (Some(from_rect), Some(to_rect)) = (self.container_rects.get(animation.from), self.container_rects.get(animation.to)) else {
+            return;
+        };
+        let slot_rect = |rect: &Rect, container: &FluidContainer, bottom: usize| {
+            let packet_height = rect.h / container.get_capacity().max(1) as f32;
+            let height = packet_height * animation.amount as f32;
+            Rect::new(rect.x, rect.y + rect.h - bottom as f32 * packet_height - height, rect.w, height)
+        };
+        // From where the liquid sat in the source to where it lands, arcing up over the rims
+        let start = slot_rect(from_rect, containers[animation.from], animation.from_filled);
+        let end = slot_rect(to_rect, containers[animation.to], animation.to_filled - animation.amount);
+        let t = ((get_time() - animation.start) / animation.duration).clamp(0.0, 1.0) as f32;
+        let eased = t * t * (3.0 - 2.0 * t);
+        let lift = (t * std::f32::consts::PI).sin() * from_rect.h.min(to_rect.h) * 0.3;
+        let rect = Rect::new(
+            start.x + (end.x - start.x) * eased,
+            start.y + (end.y - start.y) * eased - lift,
+            start.w + (end.w - start.w) * eased,
+            start.h + (end.h - start.h) * eased,
         );
+        self.render_packet_with_label(&animation.packet, false, rect, None, true);
+    }
+
+    fn render_move_arrow(&mut self) {
+        let Some((from, to, until)) = self.move_arrow else {
+            return;
+        };
+        let remaining = until - get_time();
+        let (Some(from_rect), Some(to_rect)) = (self.container_rects.get(from), self.container_rects.get(to)) else {
+            self.move_arrow = None;
+            return;
+        };
+        if remaining <= 0.0 {
+            self.move_arrow = None;
+            return;
+        }
+        // Fades out over its lifetime, starting half see-through
+        let mut color = self.theme.text;
+        color.a = 0.5 * (remaining / MOVE_ARROW_DURATION) as f32;
+        let start = vec2(from_rect.center().x, from_rect.y - 5.0);
+        let end = vec2(to_rect.center().x, to_rect.y - 5.0);
+        let thickness = 4.0;
+        draw_line(start.x, start.y, end.x, end.y, thickness, color);
+        let direction = (end - start).normalize_or_zero();
+        if direction == Vec2::ZERO {
+            return;
+        }
+        let head_length = (from_rect.w.min(to_rect.w) * 0.3).clamp(8.0, 20.0);
+        let back = end - direction * head_length;
+        let side = direction.perp() * head_length * 0.5;
+        draw_triangle(end, back + side, back - side, color);
+    }
+
+    fn render_status_text(&self, text: &str, area: Rect) {
+        let size = measure_text(text, None, 20, 1.0);
+        draw_rectangle(area.x, area.y, size.width + 10.0, size.height + 10.0, self.theme.background);
+        draw_text(text, area.x + 5.0, area.y + size.height + 5.0, 20.0, self.theme.text);
+    }
+
+    fn render_perf_overlay(&self) {
+        let text = format!(
+            "{:.1} ms ({} fps), {} hit records, {} packets",
+            get_frame_time() * 1000.0,
+            get_fps(),
+            self.hit_test.len(),
+            self.packets_drawn,
+        );
+        let size = measure_text(&text, None, 16, 1.0);
+        let x = self.x + self.width - size.width - 5.0;
+        draw_rectangle(x - 5.0, self.y, size.width + 10.0, size.height + 10.0, self.theme.background);
+        draw_text(&text, x, self.y + size.height + 5.0, 16.0, self.theme.text);
     }
 
     pub fn render_text(
@@ -206,35 +695,76 @@ impl Renderer {
         rect: Rect,
         hit_item: Option<HitItem>,
     ) {
+        self.render_packet_with_label(packet, selected, rect, hit_item, true);
+    }
+    fn render_packet_with_label(
+        &mut self,
+        packet: &FluidPacket,
+        selected: bool,
+        rect: Rect,
+        hit_item: Option<HitItem>,
+        labeled: bool,
+    ) {
+        self.packets_drawn += 1;
         if let Some(item) = hit_item {
             let order = self.next_order();
             self.hit_test.push(rect, item, order);
         }
 
         match packet {
-            FluidPacket::Empty => {
-                draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 2.0, DARKGRAY);
+            FluidPacket::Empty => match self.theme.empty_packet {
+                EmptyPacketStyle::Outline => {
+                    draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 2.0, self.theme.line);
+                }
+                EmptyPacketStyle::FaintFill => {
+                    let color = Color { a: EMPTY_FILL_ALPHA, ..self.theme.line };
+                    draw_rectangle(rect.x, rect.y, rect.w, rect.h, color);
+                }
+                EmptyPacketStyle::Transparent => {}
+                EmptyPacketStyle::DottedOutline => draw_dotted_rectangle_lines(rect, 2.0, self.theme.line),
+            },
+            FluidPacket::Blocked => {
+                draw_rectangle(rect.x, rect.y, rect.w, rect.h, DARKGRAY);
+                draw_line(rect.x, rect.y, rect.x + rect.w, rect.y + rect.h, 2.0, self.theme.line);
+                draw_line(rect.x + rect.w, rect.y, rect.x, rect.y + rect.h, 2.0, self.theme.line);
             }
-            FluidPacket::Fluid { color_id: _ } => {
+            FluidPacket::Wildcard => {
+                draw_rectangle(rect.x, rect.y, rect.w, rect.h, LIGHTGRAY);
+                if labeled {
+                    self.render_packet_label(packet, rect);
+                }
+            }
+            FluidPacket::Fluid { revealed: false, .. } => {
+                draw_rectangle(rect.x, rect.y, rect.w, rect.h, GRAY);
+                if labeled {
+                    self.render_text("?", rect, WHITE);
+                }
+            }
+            FluidPacket::Fluid { .. } => {
                 let color = packet.get_color().unwrap_or(WHITE);
                 draw_rectangle(rect.x, rect.y, rect.w, rect.h, color);
-                let text_rect = Rect::new(
-                    rect.x + TEXT_PADDING,
-                    rect.y + TEXT_PADDING,
-                    rect.w - 2.0 * TEXT_PADDING,
-                    rect.h - 2.0 * TEXT_PADDING,
-                );
-                self.render_text(
-                    &packet.get_letter_representation(),
-                    text_rect,
-                    WHITE,
-                );
+                if labeled {
+                    self.render_packet_label(packet, rect);
+                }
             }
         }
         if selected {
-            draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, SELECTION_BORDER, WHITE);
+            draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, SELECTION_BORDER, self.theme.selection);
         }
     }
+    fn render_packet_label(&self, packet: &FluidPacket, rect: Rect) {
+        let text_rect = Rect::new(
+            rect.x + self.layout.text_padding,
+            rect.y + self.layout.text_padding,
+            rect.w - 2.0 * self.layout.text_padding,
+            rect.h - 2.0 * self.layout.text_padding,
+        );
+        self.render_text(
+            &packet.get_letter_representation(),
+            text_rect,
+            WHITE,
+        );
+    }
     pub fn render_container(
         &mut self,
         container: &FluidContainer,
@@ -250,12 +780,33 @@ impl Renderer {
             },
             order,
         );
+        if self.container_rects.len() <= container_index {
+            self.container_rects.resize(container_index + 1, Rect::default());
+        }
+        self.container_rects[container_index] = rect;
 
+        if container.get_capacity() == 0 {
+            draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 3.0, self.theme.line);
+            return;
+        }
         let packet_height = rect.h / container.get_capacity() as f32;
-        for (i, packet) in container.get_packets().iter().enumerate() {
+        let label_runs = packet_height < self.layout.min_labeled_packet_height;
+        let packets = container.get_packets();
+        // Packets at or above this index are still in the air and get drawn by render_pour_animation
+        let in_flight = self.packets_in_flight(container_index);
+        let landed_below = packets
+            .iter()
+            .rposition(|p| !p.is_empty())
+            .map_or(0, |top| (top + 1).saturating_sub(in_flight));
+        let mut run_start = 0;
+        for (i, packet) in packets.iter().enumerate() {
             let packet_y = rect.y + rect.h - (i as f32 + 1.0) * packet_height;
-            if !packet.is_empty() {
-                self.render_packet(
+            if i > 0 && packets[i - 1] != *packet {
+                run_start = i;
+            }
+            let in_air = in_flight > 0 && i >= landed_below;
+            if !packet.is_empty() && !in_air {
+                self.render_packet_with_label(
                     packet,
                     false,
                     Rect::new(rect.x, packet_y, rect.w, packet_height),
@@ -263,23 +814,64 @@ impl Renderer {
                         container_index,
                         packet_index: i,
                     }),
+                    !label_runs,
                 );
+                if label_runs && packets.get(i + 1) != Some(packet) {
+                    let run_height = (i - run_start + 1) as f32 * packet_height;
+                    self.render_packet_label(packet, Rect::new(rect.x, packet_y, rect.w, run_height));
+                }
+                if self.highlighted_packets.contains(&(container_index, i)) {
+                    draw_rectangle_lines(rect.x, packet_y, rect.w, packet_height, SELECTION_BORDER, self.theme.selection);
+                }
             }
-            if i < container.get_capacity() - 1 {
+            if self.show_dividers && i < container.get_capacity() - 1 {
                 if packet_height <= 2.0 && i % (container.get_capacity() / 10 + 1) != 0 {
                     continue;
                 }
-                let left_edge = rect.x + (rect.w * CONTAINER_LINE_PADDING);
-                let right_edge = rect.x + rect.w - (rect.w * CONTAINER_LINE_PADDING);
-                draw_line(left_edge, packet_y, right_edge, packet_y, 2.0, DARKGRAY);
+                let left_edge = rect.x + (rect.w * self.layout.container_line_padding);
+                let right_edge = rect.x + rect.w - (rect.w * self.layout.container_line_padding);
+                draw_line(left_edge, packet_y, right_edge, packet_y, 2.0, self.theme.line);
             }
         }
-        draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 3.0, DARKGRAY);
+        draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 3.0, self.theme.line);
+        if let Some(label) = container.get_label() {
+            // Sits in the padding just above the container
+            let label_rect = Rect::new(rect.x, rect.y - self.layout.container_padding_vertical, rect.w, self.layout.container_padding_vertical);
+            self.render_text(label, label_rect, self.theme.secondary_text);
+        }
         // DEBUG draw entropy near the top
         let entropy = container.get_entropy();
-        draw_text(&format!("Entropy: {}", entropy), rect.x + 5.0, rect.y + 5.0, 16.0, WHITE);
+        draw_text(&format!("Entropy: {}", entropy), rect.x + 5.0, rect.y + 5.0, 16.0, self.theme.text);
+        if self.highlighted.contains(&container_index) {
+            draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, SELECTION_BORDER, YELLOW);
+        }
         if selected {
-            draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, SELECTION_BORDER, WHITE);
+            draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, SELECTION_BORDER, self.theme.selection);
+        }
+        if self.is_flashing(container_index) {
+            draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, SELECTION_BORDER, RED);
+        }
+    }
+    /// Draws the solved arrangement for reference. Nothing here can be clicked.
+    pub fn render_target_panel(&self, target: &[FluidContainer], rect: Rect) {
+        if target.is_empty() {
+            return;
+        }
+        let header_height = (rect.h * 0.1).min(30.0);
+        self.render_text("Goal", Rect::new(rect.x, rect.y, rect.w, header_height), self.theme.secondary_text);
+        let spacing = self.layout.swatch_padding_horizontal;
+        let column_width = (rect.w - spacing * (target.len() as f32 - 1.0)) / target.len() as f32;
+        let column_height = rect.h - header_height - spacing;
+        let column_y = rect.y + header_height + spacing;
+        for (i, container) in target.iter().enumerate() {
+            let column_x = rect.x + i as f32 * (column_width + spacing);
+            let packet_height = column_height / container.get_capacity().max(1) as f32;
+            for (j, packet) in container.get_packets().iter().enumerate() {
+                let color = packet.get_color().unwrap_or(self.theme.background);
+                let packet_y = column_y + column_height - (j as f32 + 1.0) * packet_height;
+                draw_rectangle(column_x, packet_y, column_width, packet_height, color);
+            }
+            draw_rectangle_lines(column_x, column_y, column_width, column_height, 2.0, self.theme.line);
         }
     }
     pub fn render_container_lineup(
@@ -290,11 +882,11 @@ impl Renderer {
         rect: Rect,
     ) {
         let container_count = containers.len() as f32;
-        let total_spacing = CONTAINER_PADDING_HORIZONTAL * (container_count - 1.0);
+        let total_spacing = self.layout.container_padding_horizontal * (container_count - 1.0);
         let container_width = (rect.w - total_spacing) / container_count;
         for (i, container) in containers.iter().enumerate() {
             let container_index = start_index + i;
-            let container_x = rect.x + i as f32 * (container_width + CONTAINER_PADDING_HORIZONTAL);
+            let container_x = rect.x + i as f32 * (container_width + self.layout.container_padding_horizontal);
             self.render_container(
                 container,
                 container_index,
@@ -315,7 +907,7 @@ impl Renderer {
             return;
         }
         let rows = container_count.div_ceil(max_columns);
-        let total_spacing_y = CONTAINER_PADDING_VERTICAL * (rows as f32 - 1.0);
+        let total_spacing_y = self.layout.container_padding_vertical * (rows as f32 - 1.0);
         let container_height = (rect.h - total_spacing_y) / rows as f32;
         let columns = container_count.div_ceil(rows);
 
@@ -323,7 +915,7 @@ impl Renderer {
             let start_idx = row * columns;
             let end_idx = (start_idx + columns).min(container_count);
             let row_containers: Vec<_> = containers[start_idx..end_idx].to_vec();
-            let container_y = rect.y + row as f32 * (container_height + CONTAINER_PADDING_VERTICAL);
+            let container_y = rect.y + row as f32 * (container_height + self.layout.container_padding_vertical);
             self.render_container_lineup(
                 &row_containers,
                 selected,
@@ -342,19 +934,33 @@ impl Renderer {
         if swatch_count == 0.0 {
             return;
         }
-        let spacing = 5.0;
+        let spacing = self.layout.swatch_padding_horizontal;
         let total_spacing = spacing * (swatch_count - 1.0);
         let swatch_width = (rect.w - total_spacing) / swatch_count;
         for (i, packet) in swatches.iter().enumerate() {
             let swatch_x = rect.x + i as f32 * (swatch_width + spacing);
+            let swatch_rect = Rect::new(swatch_x, rect.y, swatch_width, rect.h);
             self.render_packet(
                 packet,
                 Some(i) == selected,
-                Rect::new(swatch_x, rect.y, swatch_width, rect.h),
+                swatch_rect,
                 Some(HitItem::Swatch { index: i }),
             );
+            if packet.get_color_id().is_some_and(|id| self.completed_colors.contains(&id)) {
+                self.render_checkmark(swatch_rect);
+            }
         }
     }
+
+    // A small tick in the top right corner of the rect
+    fn render_checkmark(&self, rect: Rect) {
+        let size = rect.w.min(rect.h) * 0.3;
+        let x = rect.x + rect.w - size - 3.0;
+        let y = rect.y + 3.0;
+        let thickness = (size / 6.0).max(2.0);
+        draw_line(x, y + size * 0.55, x + size * 0.35, y + size * 0.9, thickness, self.theme.text);
+        draw_line(x + size * 0.35, y + size * 0.9, x + size, y + size * 0.1, thickness, self.theme.text);
+    }
     pub fn render_button (
         &mut self,
         button: &Button,
@@ -365,12 +971,12 @@ impl Renderer {
         self.hit_test.push(rect, HitItem::Button { function: button.get_action() }, order);
 
         draw_rectangle(rect.x, rect.y, rect.w, rect.h, button.get_color());
-        draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 2.0, BLACK);
+        draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 2.0, self.theme.background);
         let text_rect = Rect::new(
-            rect.x + TEXT_PADDING,
-            rect.y + TEXT_PADDING,
-            rect.w - 2.0 * TEXT_PADDING,
-            rect.h - 2.0 * TEXT_PADDING,
+            rect.x + self.layout.text_padding,
+            rect.y + self.layout.text_padding,
+            rect.w - 2.0 * self.layout.text_padding,
+            rect.h - 2.0 * self.layout.text_padding,
         );
         self.render_text(
             button.get_label(),
@@ -378,7 +984,7 @@ impl Renderer {
             WHITE,
         );
         if selected {
-            draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, SELECTION_BORDER, WHITE);
+            draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, SELECTION_BORDER, self.theme.selection);
         }
     }
     pub fn render_button_lineup(
@@ -391,10 +997,10 @@ impl Renderer {
         if button_count == 0.0 {
             return;
         }
-        let total_spacing = BUTTON_PADDING_HORIZONTAL * (button_count - 1.0);
+        let total_spacing = self.layout.button_padding_horizontal * (button_count - 1.0);
         let button_width = (rect.w - total_spacing) / button_count;
         for (i, button) in buttons.iter().enumerate() {
-            let button_x = rect.x + i as f32 * (button_width + BUTTON_PADDING_HORIZONTAL);
+            let button_x = rect.x + i as f32 * (button_width + self.layout.button_padding_horizontal);
             self.render_button(
                 button,
                 Some(i) == selected,
@@ -403,3 +1009,29 @@ impl Renderer {
         }
     }
 }
+
+fn draw_dotted_rectangle_lines(rect: Rect, thickness: f32, color: Color) {
+    let corners = [
+        (rect.x, rect.y),
+        (rect.x + rect.w, rect.y),
+        (rect.x + rect.w, rect.y + rect.h),
+        (rect.x, rect.y + rect.h),
+    ];
+    for (i, &(x1, y1)) in corners.iter().enumerate() {
+        let (x2, y2) = corners[(i + 1) % corners.len()];
+        let length = (x2 - x1).hypot(y2 - y1);
+        let mut start = 0.0;
+        while start < length {
+            let end = (start + DOT_LENGTH).min(length);
+            draw_line(
+                x1 + (x2 - x1) * start / length,
+                y1 + (y2 - y1) * start / length,
+                x1 + (x2 - x1) * end / length,
+                y1 + (y2 - y1) * end / length,
+                thickness,
+                color,
+            );
+            start += 2.0 * DOT_LENGTH;
+        }
+    }
+}
@@ -1,35 +1,70 @@
 use crate::model::*;
-use crate::gameplay::*;
+use crate::packed::PackedState;
+use std::cmp::Reverse;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::collections::VecDeque;
 use rayon::prelude::*;
 use macroquad::prelude::debug;
 use rand::prelude::*;
 
 use std::sync::{
-    Arc,
+    Arc, Mutex,
     atomic::{AtomicBool, Ordering},
 };
+use std::sync::LazyLock;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Measurements from one solver run, for comparing heuristics without scraping the debug log.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SolveStats {
+    pub nodes_expanded: usize,
+    /// Moves tried from expanded states, before duplicates of visited states are thrown away.
+    pub moves_generated: usize,
+    pub max_frontier: usize,
+    pub elapsed: Duration,
+    pub solution_len: Option<usize>,
+}
+
+/// Give up on a greedy playthrough after this many moves, it's wandering rather than converging.
+const MAX_GREEDY_MOVES: usize = 10_000;
+
+/// A solution from `GameState::best_effort_solution`, marked with how much it can be trusted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BestEffortSolution {
+    /// Found by the exact solver, so no shorter solution exists.
+    Optimal(Vec<MoveAction>),
+    /// The exact solver ran out of time, this one works but may be longer than needed.
+    Greedy(Vec<MoveAction>),
+    /// Neither approach found a way to solve the board.
+    Unsolved,
+}
+
+/// What `GameState::explore_layers` should do with a board it just reached.
+enum Explore {
+    /// Look at the moves out of it in the next layer.
+    Expand,
+    /// Count it as reached, but go no further from it.
+    Leave,
+    /// Give up on the whole search.
+    Stop,
+}
+
+type SolvabilityKey = (PourRule, PackedState);
+
+// Past this many boards the cache is emptied and starts over, so a long session doesn't keep every board it checked.
+const MAX_SOLVABILITY_CACHE_SIZE: usize = 100_000;
+
+/// Whether boards seen recently can still be solved by moves, keyed by their rule and canonical packing.
+static SOLVABILITY_CACHE: LazyLock<Mutex<HashMap<SolvabilityKey, bool>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
 
 #[derive(Clone)]
 struct GameStateWithHistory {
     state: GameState,
     history: Vec<MoveAction>,
 }
-impl GameStateWithHistory {
-    pub fn merge(mut self, other: GameStateWithHistory) -> GameStateWithHistory {
-        assert !(self.state == other.state, "Cannot merge different game states");
-        let shortest_history = if self.history.len() < other.history.len() {
-            self.history.clone()
-        } else {
-            other.history.clone()
-        };
-        GameStateWithHistory {
-            state: self.state,
-            history: shortest_history,
-        }
-    }
-}
 impl PartialEq for GameStateWithHistory {
     fn eq(&self, other: &Self) -> bool {
         self.state == other.state
@@ -37,32 +72,301 @@ impl PartialEq for GameStateWithHistory {
 }
 impl Eq for GameStateWithHistory {}
 
+/// Breadth-first search over forward moves, so the first solution found is the shortest.
+/// This is where solving actually happens, `GameState::solve` and friends just run one of these.
+/// A solver is used up by a single run.
 pub struct Solver {
     starting_state: GameState,
-    considering_states: Vec<GameStateWithHistory>,
-    visited_states: Vec<GameStateWithHistory>,
+    considering_states: VecDeque<GameStateWithHistory>,
+    // States are compared with their containers sorted, since container order doesn't matter.
+    // They're kept packed, hashing and storing full containers for every visited board is most of the cost.
+    visited_states: HashSet<PackedState>,
+    stats: SolveStats,
+    prefer_clearing_moves: bool,
+    prune_symmetric_containers: bool,
+    deadline: Option<Instant>,
+    timed_out: bool,
 }
 
 impl Solver {
     pub fn new(starting_state: GameState) -> Solver {
-        Solver {
+        let mut solver = Solver {
             starting_state: starting_state.clone(),
-            considering_states: vec![GameStateWithHistory {
-                state: starting_state,
-                history: vec![],
-            }],
-            visited_states: vec![],
+            considering_states: VecDeque::new(),
+            visited_states: HashSet::new(),
+            stats: SolveStats::default(),
+            prefer_clearing_moves: true,
+            prune_symmetric_containers: true,
+            deadline: None,
+            timed_out: false,
+        };
+        solver.consider_state(GameStateWithHistory {
+            state: starting_state,
+            history: vec![],
+        });
+        solver
+    }
+
+    /// Queues a state unless an equivalent one has been seen. Returns whether it was queued.
+    fn consider_state(&mut self, state_with_history: GameStateWithHistory) -> bool {
+        if !self.visited_states.insert(PackedState::canonical(&state_with_history.state)) {
+            return false;
         }
+        self.considering_states.push_back(state_with_history);
+        true
     }
-    fn consider_state(&mut self, state_with_history: GameStateWithHistory) {
-        if !self
-            .visited_states
-            .iter()
-            .any(|s| s.state == state_with_history.state)
-        {
-            self.considering_states.push(state_with_history);
+
+    /// On by default. Turning it off expands moves in plain index order, for benchmarking the ordering against.
+    /// Either way the search is breadth-first, so this only changes which of several shortest solutions is found and how soon.
+    pub fn set_prefer_clearing_moves(&mut self, prefer: bool) {
+        self.prefer_clearing_moves = prefer;
+    }
+
+    /// On by default. Identical containers are interchangeable, so only the first of them is poured from,
+    /// and only the first of them is poured into. Turning it off tries every pair, for benchmarking.
+    pub fn set_prune_symmetric_containers(&mut self, prune: bool) {
+        self.prune_symmetric_containers = prune;
+    }
+
+    /// Gives up on the search, returning no solution, once this much time has passed from now.
+    pub fn set_time_budget(&mut self, budget: Duration) {
+        self.deadline = Some(Instant::now() + budget);
+    }
+
+    /// Whether the last run stopped because the time budget ran out, rather than finishing.
+    pub fn timed_out(&self) -> bool {
+        self.timed_out
+    }
+
+    pub fn get_stats(&self) -> SolveStats {
+        self.stats
+    }
+
+    pub fn run(&mut self) -> Option<Vec<MoveAction>> {
+        self.run_until_cancelled(&AtomicBool::new(false))
+    }
+
+    /// Like `run`, but setting `cancel` from another thread stops the search and gives back no solution.
+    pub fn run_until_cancelled(&mut self, cancel: &AtomicBool) -> Option<Vec<MoveAction>> {
+        let start_time = Instant::now();
+        let solution = self.search(cancel);
+        self.stats.elapsed += start_time.elapsed();
+        self.stats.solution_len = solution.as_ref().map(|history| history.len());
+        solution
+    }
+
+    fn search(&mut self, cancel: &AtomicBool) -> Option<Vec<MoveAction>> {
+        if !self.starting_state.pour_rule.allows_solving() {
+            debug!("The solver doesn't run under {:?} pours.", self.starting_state.pour_rule);
+            return None;
+        }
+        if !self.starting_state.is_fully_revealed() {
+            debug!("The solver doesn't run while packets are still hidden.");
+            return None;
+        }
+        if self.starting_state.is_solved() {
+            return Some(vec![]);
+        }
+        while let Some(current) = self.considering_states.pop_front() {
+            if cancel.load(Ordering::Relaxed) {
+                debug!("Solver cancelled after visiting {} states.", self.visited_states.len());
+                return None;
+            }
+            if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                debug!("Solver ran out of time after visiting {} states.", self.visited_states.len());
+                self.timed_out = true;
+                return None;
+            }
+            self.stats.nodes_expanded += 1;
+            let mut moves = if self.prune_symmetric_containers {
+                current.state.collect_possible_moves(true, true)
+            } else {
+                current.state.collect_possible_moves(false, false)
+            };
+            self.stats.moves_generated += moves.len();
+            if !self.prefer_clearing_moves {
+                moves.sort_by_key(|mv| (mv.from_container, mv.to_container));
+            }
+            for mv in moves {
+                let mut next_state = current.state.clone();
+                next_state.apply_move(&mv);
+                let mut history = current.history.clone();
+                history.push(mv);
+                if next_state.is_solved() {
+                    debug!("Found a solution in {} moves after visiting {} states.", history.len(), self.visited_states.len());
+                    return Some(history);
+                }
+                self.consider_state(GameStateWithHistory {
+                    state: next_state,
+                    history,
+                });
+            }
+            self.stats.max_frontier = self.stats.max_frontier.max(self.considering_states.len());
+        }
+        debug!("No solution after visiting {} states.", self.visited_states.len());
+        None
+    }
+
+    /// Solves on a separate thread so the UI keeps drawing frames while it works.
+    pub fn spawn(state: GameState) -> SolveHandle {
+        Self::spawn_with_deadline(state, None)
+    }
+
+    /// Like `spawn`, but gives up with `SolveStatus::TimedOut` once `budget` has passed.
+    pub fn spawn_with_budget(state: GameState, budget: Duration) -> SolveHandle {
+        Self::spawn_with_deadline(state, Some(budget))
+    }
+
+    fn spawn_with_deadline(state: GameState, budget: Option<Duration>) -> SolveHandle {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let result = Arc::new(Mutex::new(None));
+        let thread_state = state.clone();
+        let thread_cancel = Arc::clone(&cancel);
+        let thread_result = Arc::clone(&result);
+        thread::spawn(move || {
+            let mut solver = Solver::new(thread_state);
+            if let Some(budget) = budget {
+                solver.set_time_budget(budget);
+            }
+            let status = match solver.run_until_cancelled(&thread_cancel) {
+                Some(solution) => SolveStatus::Solved(solution),
+                None if solver.timed_out() => SolveStatus::TimedOut,
+                None => SolveStatus::Unsolvable,
+            };
+            if let Ok(mut result) = thread_result.lock() {
+                *result = Some(status);
+            }
+        });
+        SolveHandle { state, cancel, result }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SolveStatus {
+    Running,
+    Solved(Vec<MoveAction>),
+    Unsolvable,
+    /// Ran out of the time budget it was spawned with before finding an answer either way.
+    TimedOut,
+    Cancelled,
+}
+
+/// A solve running in the background. The board may be edited while it runs,
+/// so check `is_for` before using the solution. Dropping the handle cancels the search.
+pub struct SolveHandle {
+    state: GameState,
+    cancel: Arc<AtomicBool>,
+    result: Arc<Mutex<Option<SolveStatus>>>,
+}
+
+impl SolveHandle {
+    pub fn poll(&self) -> SolveStatus {
+        let Ok(result) = self.result.lock() else {
+            return SolveStatus::Cancelled;
+        };
+        match result.as_ref() {
+            None => SolveStatus::Running,
+            Some(_) if self.cancel.load(Ordering::Relaxed) => SolveStatus::Cancelled,
+            Some(status) => status.clone(),
         }
     }
+
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether this solve was started from exactly this board, so its moves still line up.
+    pub fn is_for(&self, state: &GameState) -> bool {
+        self.state.eq_exact(state)
+    }
+}
+
+impl Drop for SolveHandle {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+/// Depth-first search that yields every solution strictly shorter than the last one it yielded.
+/// The first solution comes quickly, and the last one yielded before the iterator ends is optimal.
+/// All of the search state lives in the struct, so a caller can stop and resume at any point.
+pub struct SolutionIter {
+    stack: Vec<GameStateWithHistory>,
+    best_depths: HashMap<PackedState, usize>,
+    bound: usize,
+    pending: Option<Vec<MoveAction>>,
+}
+
+impl SolutionIter {
+    fn new(state: &GameState) -> SolutionIter {
+        let mut iter = SolutionIter {
+            stack: vec![],
+            best_depths: HashMap::new(),
+            bound: usize::MAX,
+            pending: None,
+        };
+        // Same as the solver: nothing under a rule it can't plan for, and no peeking at hidden packets
+        if !state.pour_rule.allows_solving() || !state.is_fully_revealed() {
+            return iter;
+        }
+        if state.is_solved() {
+            iter.bound = 0;
+            iter.pending = Some(vec![]);
+            return iter;
+        }
+        iter.best_depths.insert(PackedState::canonical(state), 0);
+        iter.stack.push(GameStateWithHistory {
+            state: state.clone(),
+            history: vec![],
+        });
+        iter
+    }
+}
+
+impl Iterator for SolutionIter {
+    type Item = Vec<MoveAction>;
+
+    fn next(&mut self) -> Option<Vec<MoveAction>> {
+        if let Some(solution) = self.pending.take() {
+            return Some(solution);
+        }
+        while let Some(current) = self.stack.pop() {
+            let depth = current.history.len() + 1;
+            if depth >= self.bound {
+                continue;
+            }
+            let mut children = vec![];
+            let mut found = None;
+            for mv in current.state.get_possible_moves() {
+                let mut next_state = current.state.clone();
+                next_state.apply_move(&mv);
+                let key = PackedState::canonical(&next_state);
+                if self.best_depths.get(&key).is_some_and(|&best| best <= depth) {
+                    continue;
+                }
+                self.best_depths.insert(key, depth);
+                let mut history = current.history.clone();
+                history.push(mv);
+                if next_state.is_solved() {
+                    found = Some(history);
+                    continue;
+                }
+                children.push(GameStateWithHistory {
+                    state: next_state,
+                    history,
+                });
+            }
+            // Lowest entropy ends up on top of the stack so the search dives toward sorted boards first.
+            children.sort_by_key(|child| Reverse(child.state.get_entropy()));
+            self.stack.extend(children);
+            if let Some(solution) = found {
+                debug!("Found a {} move solution, searching for a shorter one.", solution.len());
+                self.bound = solution.len();
+                return Some(solution);
+            }
+        }
+        None
+    }
 }
 
 impl GameState {
@@ -124,6 +428,12 @@ impl GameState {
 
     pub fn fast_is_maybe_solvable(&self) -> Option<bool> {
         // Returns Some(true) if definitely solvable, Some(false) if definitely unsolvable, None if unknown
+        if self.has_blocked_packets() {
+            return self.without_blocked_packets().fast_is_maybe_solvable();
+        }
+        if self.has_wildcards() {
+            return if self.is_solved() { Some(true) } else { None };
+        }
         if self.is_solved() {
             debug!("Game state is already solved.");
             return Some(true);
@@ -172,11 +482,29 @@ impl GameState {
         }
     }
 
+    /// Parses a board and panics unless `is_solvable` agrees it can be arranged into a solved board.
+    /// For pinning down boards the solvability check has gotten wrong before.
+    #[cfg(test)]
+    #[track_caller]
+    pub fn debug_assert_solvable(repr: &str) -> GameState {
+        let state = GameState::new_from_repr(repr);
+        assert!(state.is_solvable(), "expected a solvable board:\n{}", repr);
+        state
+    }
+
     pub fn is_solvable(&self) -> bool {
         // A full check for solvability using recursive subset enumeration
         // If this returns true, there is definitely a way to arrange the liquids that is solved, although it might not be reachable entirely by moves.
         // If false, there is definitely no way to arrange the liquids that is solved.
         // This is a computationally expensive check, so we first run the fast checks.
+        // Blocked packets all break before the end, so the arrangement that matters is the one without them.
+        if self.has_blocked_packets() {
+            return self.without_blocked_packets().is_solvable();
+        }
+        // Wildcards can make up any color's numbers, so counting can't settle it. Only solving can.
+        if self.has_wildcards() {
+            return self.solve().is_some();
+        }
         if let Some(result) = self.fast_is_maybe_solvable() {
             return result;
         }
@@ -192,7 +520,7 @@ impl GameState {
             *container_size_to_count_map.entry(c).or_insert(0) += 1;
         }
         let mut container_size_and_count_vec: Vec<(usize, usize)> = container_size_to_count_map.iter().map(|(size, count)| (*size, *count)).collect();
-        container_size_and_count_vec.sort_by(|a, b| b.0.cmp(&a.0));
+        container_size_and_count_vec.sort_by_key(|c| Reverse(c.0));
 
         let mut liquid_size_vec: Vec<usize> = self
             .get_available_colors_with_count()
@@ -322,32 +650,131 @@ impl GameState {
         })
     }
 
+    /// True if just stacking each color onto matching colors solves the board, with no empty container used as a buffer.
+    /// Only whole runs are poured, preferring containers that already hold one color, so a false here can
+    /// occasionally be wrong but a true never is. Useful for throwing away boring generated boards.
+    pub fn is_trivially_solved_by_gathering(&self) -> bool {
+        // Every pour merges two runs into one, so this stops after at most one pour per run.
+        let mut state = self.clone();
+        loop {
+            if state.is_solved() {
+                return true;
+            }
+            let merge = state
+                .get_possible_moves()
+                .into_iter()
+                .filter(|mv| {
+                    let from_container = &state.fluid_containers[mv.from_container];
+                    let to_container = &state.fluid_containers[mv.to_container];
+                    !to_container.is_empty() && mv.amount == from_container.get_top_fluid_depth()
+                })
+                .max_by_key(|mv| {
+                    let to_container = &state.fluid_containers[mv.to_container];
+                    to_container.get_top_fluid_depth() == to_container.get_filled_amount()
+                });
+            match merge {
+                Some(mv) => {
+                    state.apply_move(&mv);
+                }
+                None => return false,
+            }
+        }
+    }
+
+    /// How many moves `get_possible_moves` would return, without building them.
+    pub fn legal_move_count(&self) -> usize {
+        if !self.pour_rule.allows_solving() {
+            return 0;
+        }
+        let containers = &self.fluid_containers;
+        let mut count = 0;
+        for (from_index, from_container) in containers.iter().enumerate() {
+            if from_container.is_empty() {
+                continue;
+            }
+            for (to_index, to_container) in containers.iter().enumerate() {
+                if from_index == to_index {
+                    continue;
+                }
+                // Same deduplication as get_possible_moves, by looking back instead of keeping a list
+                let seen_before = containers[..to_index]
+                    .iter()
+                    .enumerate()
+                    .any(|(i, c)| i != from_index && c == to_container);
+                if !seen_before && from_container.get_pourable_amount_with_rule(to_container, self.pour_rule) > 0 {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
     pub fn get_possible_moves(&self) -> Vec<MoveAction> {
+        self.collect_possible_moves(true, false)
+    }
+
+    /// For each container, the index of the first container identical to it, which may be itself.
+    fn symmetry_groups(&self) -> Vec<usize> {
+        let containers = &self.fluid_containers;
+        (0..containers.len())
+            .map(|index| {
+                (0..index)
+                    .find(|&earlier| containers[earlier] == containers[index])
+                    .unwrap_or(index)
+            })
+            .collect()
+    }
+
+    /// Pouring into either of two identical containers gives equivalent states, and so does pouring out of either,
+    /// so the flags skip all but the first of each group of identical destinations and sources.
+    fn collect_possible_moves(&self, dedupe_destinations: bool, dedupe_sources: bool) -> Vec<MoveAction> {
         let mut moves = vec![];
-        for color in self.get_top_colors() {
-            for (from_index, from_container) in self.fluid_containers.iter().enumerate() {
-                if from_container.is_empty() || from_container.get_top_fluid() != FluidPacket::new(color) {
+        if !self.pour_rule.allows_solving() {
+            return moves;
+        }
+        let groups = self.symmetry_groups();
+        for (from_index, from_container) in self.fluid_containers.iter().enumerate() {
+            if from_container.is_empty() || (dedupe_sources && groups[from_index] != from_index) {
+                continue;
+            }
+            let mut seen_groups: Vec<usize> = vec![];
+            for (to_index, to_container) in self.fluid_containers.iter().enumerate() {
+                if from_index == to_index {
                     continue;
                 }
-                for (to_index, to_container) in self.fluid_containers.iter().enumerate() {
-                    if from_index == to_index {
+                if dedupe_destinations {
+                    if seen_groups.contains(&groups[to_index]) {
                         continue;
                     }
-                    let amount = from_container.get_pourable_amount(to_container);
-                    if amount == from_container.get_top_fluid_depth() {
-                        // If the amount is less, then this move is reversible, so we only consider full pours to reduce the search space
-                        moves.push(MoveAction {
-                            from_container: from_index,
-                            to_container: to_index,
-                            amount,
-                        });
-                    }
+                    seen_groups.push(groups[to_index]);
+                }
+                // Partial pours are kept, topping off a container is sometimes the only way forward
+                let amount = from_container.get_pourable_amount_with_rule(to_container, self.pour_rule);
+                if amount > 0 {
+                    moves.push(MoveAction {
+                        from_container: from_index,
+                        to_container: to_index,
+                        amount,
+                    });
                 }
             }
         }
+        // A fixed order keeps solve() returning the same path on every run.
+        // Moves that finish a container or empty their source come first, they tend to lead to solutions sooner.
+        moves.sort_by_key(|mv| (!(self.completes_container(mv) || self.empties_source(mv)), mv.from_container, mv.to_container));
         moves
     }
 
+    fn empties_source(&self, action: &MoveAction) -> bool {
+        self.fluid_containers[action.from_container].get_filled_amount() == action.amount
+    }
+
+    fn completes_container(&self, action: &MoveAction) -> bool {
+        let to_container = &self.fluid_containers[action.to_container];
+        to_container.get_top_fluid_depth() == to_container.get_filled_amount()
+            && to_container.get_filled_amount() + action.amount == to_container.get_capacity()
+    }
+
     pub fn get_possible_reverse_moves(&self, limit_size: bool) -> Vec<MoveAction> {
         let mut moves = vec![];
         for (from_index, from_container) in self.fluid_containers.iter().enumerate() {
@@ -375,6 +802,426 @@ impl GameState {
         moves
     }
 
+    pub fn solve(&self) -> Option<Vec<MoveAction>> {
+        self.solve_with_stats().0
+    }
+
+    pub fn solve_with_stats(&self) -> (Option<Vec<MoveAction>>, SolveStats) {
+        self.solve_until_cancelled(&AtomicBool::new(false))
+    }
+
+    pub fn solve_until_cancelled(&self, cancel: &AtomicBool) -> (Option<Vec<MoveAction>>, SolveStats) {
+        let mut solver = Solver::new(self.clone());
+        let solution = solver.run_until_cancelled(cancel);
+        (solution, solver.get_stats())
+    }
+
+    /// Breadth-first over the boards reachable from this one, a layer per move, with boards that only differ in
+    /// container order counted once. `visit` gets each board when it's first reached, along with how many moves in
+    /// that was, starting with this board at 0, and decides whether to go on from it.
+    /// Returns how many moves in every board reached was, keyed by its canonical packing.
+    /// Boards are only kept packed between layers, so this is the cheap way to search when no move history is needed.
+    fn explore_layers(&self, mut visit: impl FnMut(&GameState, usize) -> Explore) -> HashMap<PackedState, usize> {
+        let mut depths: HashMap<PackedState, usize> = HashMap::new();
+        depths.insert(PackedState::canonical(self), 0);
+        let mut layer = match visit(self, 0) {
+            Explore::Expand => vec![PackedState::from_state(self)],
+            Explore::Leave | Explore::Stop => return depths,
+        };
+        let mut depth = 0;
+        while !layer.is_empty() {
+            depth += 1;
+            let mut next_layer = vec![];
+            for packed in &layer {
+                let mut state = packed.to_state();
+                state.pour_rule = self.pour_rule;
+                for mv in state.get_possible_moves() {
+                    let mut next_state = state.clone();
+                    next_state.apply_move(&mv);
+                    let key = PackedState::canonical(&next_state);
+                    if depths.contains_key(&key) {
+                        continue;
+                    }
+                    depths.insert(key, depth);
+                    match visit(&next_state, depth) {
+                        Explore::Expand => next_layer.push(PackedState::from_state(&next_state)),
+                        Explore::Leave => {}
+                        Explore::Stop => return depths,
+                    }
+                }
+            }
+            layer = next_layer;
+        }
+        depths
+    }
+
+    /// The length of the shortest solution, or None if there isn't one.
+    /// Gives the same number as `solve`, but the search only keeps packed boards rather than a move history
+    /// for every one, so it's the cheaper way to find par for a lot of boards.
+    pub fn min_moves(&self) -> Option<usize> {
+        if !self.pour_rule.allows_solving() || !self.is_fully_revealed() {
+            return None;
+        }
+        let mut min_moves = None;
+        self.explore_layers(|state, depth| {
+            if state.is_solved() {
+                min_moves = Some(depth);
+                Explore::Stop
+            } else {
+                Explore::Expand
+            }
+        });
+        min_moves
+    }
+
+    /// How many different boards can be reached in at most `max_depth` moves, this one included.
+    /// Boards that only differ in container order count once. A measure of how much freedom the opening gives.
+    /// With packets still hidden nothing past this board is counted, the same as the solver won't look.
+    pub fn reachable_states_count(&self, max_depth: usize) -> usize {
+        if !self.is_fully_revealed() {
+            return 1;
+        }
+        self.explore_layers(|_, depth| if depth < max_depth { Explore::Expand } else { Explore::Leave })
+            .len()
+    }
+
+    /// Up to `cap` different move sequences that all solve the board in the fewest moves.
+    pub fn all_optimal_solutions(&self, cap: usize) -> Vec<Vec<MoveAction>> {
+        if cap == 0 {
+            return vec![];
+        }
+        let mut solutions = vec![];
+        self.for_each_optimal_solution(&mut |solution| {
+            solutions.push(solution.to_vec());
+            solutions.len() >= cap
+        });
+        debug!("Found {} optimal solutions of {} moves.", solutions.len(), solutions.first().map_or(0, |s| s.len()));
+        solutions
+    }
+
+    /// Whether the board has exactly one shortest solution, for puzzle designers after a single intended line.
+    /// Solutions that only differ in the order of moves between unrelated containers count as the same one.
+    /// Stops looking as soon as a second, really different one turns up.
+    pub fn has_unique_optimal_solution(&self) -> bool {
+        let mut distinct: Vec<Vec<MoveAction>> = vec![];
+        self.for_each_optimal_solution(&mut |solution| {
+            let normal_form = Self::reordering_normal_form(solution);
+            if !distinct.contains(&normal_form) {
+                distinct.push(normal_form);
+            }
+            distinct.len() >= 2
+        });
+        distinct.len() == 1
+    }
+
+    /// How far every board is from this one, out to the length of the shortest solution, and that length.
+    /// None if the board can't be solved.
+    /// A move is only on a shortest solution if it lands on a board exactly one step further out,
+    /// so the searches after every shortest solution only ever follow those.
+    fn optimal_distances(&self) -> Option<(HashMap<PackedState, usize>, usize)> {
+        if !self.pour_rule.allows_solving() || !self.is_fully_revealed() {
+            return None;
+        }
+        let mut optimal = None;
+        let distances = self.explore_layers(|state, depth| match optimal {
+            // The whole layer holding the first solution is needed, anything past it isn't.
+            Some(optimal) if depth > optimal => Explore::Stop,
+            _ if state.is_solved() => {
+                optimal = Some(depth);
+                Explore::Leave
+            }
+            _ => Explore::Expand,
+        });
+        optimal.map(|optimal| (distances, optimal))
+    }
+
+    /// Calls `on_solution` with every shortest solution until it returns true.
+    fn for_each_optimal_solution(&self, on_solution: &mut dyn FnMut(&[MoveAction]) -> bool) {
+        let Some((distances, optimal)) = self.optimal_distances() else {
+            return;
+        };
+        if optimal == 0 {
+            on_solution(&[]);
+            return;
+        }
+        self.collect_optimal_paths(&distances, optimal, &mut vec![], &mut HashSet::new(), &mut false, on_solution);
+    }
+
+    fn collect_optimal_paths(
+        &self,
+        distances: &HashMap<PackedState, usize>,
+        optimal: usize,
+        path: &mut Vec<MoveAction>,
+        dead_ends: &mut HashSet<PackedState>,
+        done: &mut bool,
+        on_solution: &mut dyn FnMut(&[MoveAction]) -> bool,
+    ) -> bool {
+        // Returns whether any solution was found below this board, so boards that lead nowhere are only explored once.
+        let depth = path.len() + 1;
+        let mut found = false;
+        for mv in self.get_possible_moves() {
+            if *done {
+                return true;
+            }
+            let mut next_state = self.clone();
+            next_state.apply_move(&mv);
+            let key = PackedState::canonical(&next_state);
+            if distances.get(&key) != Some(&depth) || dead_ends.contains(&key) {
+                continue;
+            }
+            path.push(mv);
+            if next_state.is_solved() {
+                *done = on_solution(path);
+                found = true;
+            } else if depth < optimal {
+                if next_state.collect_optimal_paths(distances, optimal, path, dead_ends, done, on_solution) {
+                    found = true;
+                } else {
+                    dead_ends.insert(key);
+                }
+            }
+            path.pop();
+        }
+        found
+    }
+
+    /// The same moves, reordered into a form shared by every ordering that only swaps moves between unrelated
+    /// containers: each time, the smallest move that doesn't touch a container an earlier remaining move touches
+    /// goes next. Stones breaking and packets turning face up reach across containers, so those aren't accounted for.
+    fn reordering_normal_form(moves: &[MoveAction]) -> Vec<MoveAction> {
+        let touches = |a: &MoveAction, b: &MoveAction| {
+            a.from_container == b.from_container
+                || a.from_container == b.to_container
+                || a.to_container == b.from_container
+                || a.to_container == b.to_container
+        };
+        let mut remaining = moves.to_vec();
+        let mut normal_form = Vec::with_capacity(moves.len());
+        while !remaining.is_empty() {
+            let next = (0..remaining.len())
+                .filter(|&i| remaining[..i].iter().all(|earlier| !touches(earlier, &remaining[i])))
+                .min_by_key(|&i| (remaining[i].from_container, remaining[i].to_container, remaining[i].amount))
+                .expect("the first remaining move can always go next");
+            normal_form.push(remaining.remove(next));
+        }
+        normal_form
+    }
+
+    /// A shortest solution that makes as few partial pours as any other shortest one. A pour is partial when the
+    /// target runs out of room and leaves some of the top color behind. People mostly pour everything at once,
+    /// so these solutions read better, e.g. when writing up a board's walkthrough.
+    /// Slower than `solve`, the whole final layer has to be compared, so it's not used for the in-game hint.
+    pub fn solve_preferring_full_pours(&self) -> Option<Vec<MoveAction>> {
+        let (distances, optimal) = self.optimal_distances()?;
+        let mut fewest_partial_pours = HashMap::new();
+        let mut state = self.clone();
+        let mut solution = vec![];
+        // Every board along the way already knows the fewest partial pours left from it, so each step
+        // just takes a move that keeps to that.
+        while solution.len() < optimal {
+            let depth = solution.len() + 1;
+            let (mv, next_state, _) = state
+                .optimal_moves(&distances, depth)
+                .filter_map(|(mv, next_state)| {
+                    let partial_pours = usize::from(state.is_partial_pour(&mv))
+                        + next_state.fewest_partial_pours(&distances, optimal, depth, &mut fewest_partial_pours)?;
+                    Some((mv, next_state, partial_pours))
+                })
+                .min_by_key(|(_, _, partial_pours)| *partial_pours)
+                .expect("a board on a shortest solution has a move that carries on along one");
+            solution.push(mv);
+            state = next_state;
+        }
+        debug!(
+            "Found a {} move solution with {:?} partial pours.",
+            solution.len(),
+            self.fewest_partial_pours(&distances, optimal, 0, &mut fewest_partial_pours)
+        );
+        Some(solution)
+    }
+
+    /// The moves out of this board, `depth - 1` moves in, that stay on a shortest solution, with where each one lands.
+    fn optimal_moves<'a>(
+        &'a self,
+        distances: &'a HashMap<PackedState, usize>,
+        depth: usize,
+    ) -> impl Iterator<Item = (MoveAction, GameState)> + 'a {
+        self.get_possible_moves().into_iter().filter_map(move |mv| {
+            let mut next_state = self.clone();
+            next_state.apply_move(&mv);
+            (distances.get(&PackedState::canonical(&next_state)) == Some(&depth)).then_some((mv, next_state))
+        })
+    }
+
+    /// A pour is partial when the target runs out of room and some of the top color stays behind.
+    fn is_partial_pour(&self, action: &MoveAction) -> bool {
+        action.amount < self.fluid_containers[action.from_container].get_top_fluid_depth()
+    }
+
+    /// The fewest partial pours that finish a shortest solution from this board, `depth` moves in,
+    /// or None if no shortest solution goes through it.
+    /// Memoized by board, since many orders of the same moves meet again on the way.
+    fn fewest_partial_pours(
+        &self,
+        distances: &HashMap<PackedState, usize>,
+        optimal: usize,
+        depth: usize,
+        memo: &mut HashMap<PackedState, Option<usize>>,
+    ) -> Option<usize> {
+        if depth == optimal {
+            return self.is_solved().then_some(0);
+        }
+        let key = PackedState::canonical(self);
+        if let Some(&partial_pours) = memo.get(&key) {
+            return partial_pours;
+        }
+        let partial_pours = self
+            .optimal_moves(distances, depth + 1)
+            .filter_map(|(mv, next_state)| {
+                let rest = next_state.fewest_partial_pours(distances, optimal, depth + 1, memo)?;
+                Some(usize::from(self.is_partial_pour(&mv)) + rest)
+            })
+            .min();
+        memo.insert(key, partial_pours);
+        partial_pours
+    }
+
+    pub fn is_solvable_after(&self, action: &MoveAction) -> bool {
+        // Unlike is_solvable, which only asks whether the liquids fit the containers and so never changes with moves,
+        // this asks whether the board can still be won by actual pours after making the move.
+        // Results are memoized across calls, and every board along a found solution is recorded as solvable too,
+        // so checking each candidate move of a position mostly hits the cache.
+        let mut next_state = self.clone();
+        if !next_state.apply_move(action) {
+            return false;
+        }
+        next_state.can_be_solved_cached()
+    }
+
+    fn can_be_solved_cached(&self) -> bool {
+        let key = (self.pour_rule, PackedState::canonical(self));
+        if let Ok(cache) = SOLVABILITY_CACHE.lock()
+            && let Some(solvable) = cache.get(&key)
+        {
+            return *solvable;
+        }
+        let solution = self.solve();
+        if let Ok(mut cache) = SOLVABILITY_CACHE.lock() {
+            let solution_len = solution.as_ref().map_or(0, |solution| solution.len());
+            if cache.len() + 1 + solution_len > MAX_SOLVABILITY_CACHE_SIZE {
+                cache.clear();
+            }
+            cache.insert(key, solution.is_some());
+            if let Some(solution) = solution.as_ref() {
+                let mut replay = self.clone();
+                for mv in solution {
+                    replay.apply_move(mv);
+                    cache.insert((replay.pour_rule, PackedState::canonical(&replay)), true);
+                }
+            }
+        }
+        solution.is_some()
+    }
+
+    pub fn solve_iter(&self) -> impl Iterator<Item = Vec<MoveAction>> + use<> {
+        SolutionIter::new(self)
+    }
+
+    pub fn greedy_suggestion(&self) -> Option<MoveAction> {
+        // Instant but not optimal: the move that completes a container, otherwise the one that lowers entropy the most.
+        self.get_possible_moves()
+            .into_iter()
+            .filter(|mv| !self.is_pointless_move(mv))
+            .min_by_key(|mv| self.greedy_rank(mv))
+    }
+
+    pub fn is_pointless_move(&self, action: &MoveAction) -> bool {
+        // Moving a container's only color into an empty container changes nothing.
+        let from_container = &self.fluid_containers[action.from_container];
+        let to_container = &self.fluid_containers[action.to_container];
+        to_container.is_empty() && from_container.get_top_fluid_depth() == from_container.get_filled_amount()
+    }
+
+    fn greedy_rank(&self, action: &MoveAction) -> (bool, usize) {
+        let mut next_state = self.clone();
+        next_state.apply_move(action);
+        (!self.completes_container(action), next_state.get_entropy())
+    }
+
+    /// Keeps making the greedy move, never returning to a board it has already been on, until solved or stuck.
+    pub fn greedy_solution(&self) -> Option<Vec<MoveAction>> {
+        let mut state = self.clone();
+        let mut visited: HashSet<PackedState> = HashSet::new();
+        visited.insert(PackedState::canonical(&state));
+        let mut moves = vec![];
+        while !state.is_solved() {
+            if moves.len() >= MAX_GREEDY_MOVES {
+                return None;
+            }
+            let next_move = state
+                .get_possible_moves()
+                .into_iter()
+                .filter(|mv| !state.is_pointless_move(mv))
+                .filter(|mv| {
+                    let mut next_state = state.clone();
+                    next_state.apply_move(mv);
+                    !visited.contains(&PackedState::canonical(&next_state))
+                })
+                .min_by_key(|mv| state.greedy_rank(mv))?;
+            state.apply_move(&next_move);
+            visited.insert(PackedState::canonical(&state));
+            moves.push(next_move);
+        }
+        Some(moves)
+    }
+
+    /// Solves exactly if that finishes within `budget`, otherwise falls back to a greedy playthrough.
+    pub fn best_effort_solution(&self, budget: Duration) -> BestEffortSolution {
+        let mut solver = Solver::new(self.clone());
+        solver.set_time_budget(budget);
+        if let Some(solution) = solver.run() {
+            return BestEffortSolution::Optimal(solution);
+        }
+        if !solver.timed_out() {
+            // The search finished, so there's nothing for greedy to find either
+            return BestEffortSolution::Unsolved;
+        }
+        match self.greedy_solution() {
+            Some(solution) => BestEffortSolution::Greedy(solution),
+            None => BestEffortSolution::Unsolved,
+        }
+    }
+
+    pub fn hint(&self) -> Option<MoveAction> {
+        // The first move of the shortest solution, or None if already solved or unsolvable.
+        // Hints are asked for on the UI thread, so this stays on the plain search rather than
+        // solve_preferring_full_pours, which has to go through the whole final layer.
+        self.solve()?.first().copied()
+    }
+
+    pub fn hint_for_color(&self, color_id: usize) -> Option<MoveAction> {
+        // The first move in the shortest solution that pours the given color and can be made right now.
+        let color = FluidPacket::new(color_id);
+        if !self.fluid_containers.iter().any(|c| c.get_top_fluid() == color) {
+            return None;
+        }
+        let solution = self.solve()?;
+        let mut replay = self.clone();
+        for mv in solution {
+            let from = &self.fluid_containers[mv.from_container];
+            let to = &self.fluid_containers[mv.to_container];
+            let pours_color = replay.fluid_containers[mv.from_container].get_top_fluid() == color;
+            if pours_color && from.get_top_fluid() == color && from.could_pour_into_with_rule(to, self.pour_rule) {
+                return Some(MoveAction {
+                    amount: from.get_pourable_amount_with_rule(to, self.pour_rule),
+                    ..mv
+                });
+            }
+            replay.apply_move(&mv);
+        }
+        None
+    }
+
     pub fn apply_random_move(&mut self) -> bool {
         let possible_moves = self.get_possible_moves();
         if possible_moves.is_empty() {
@@ -439,4 +1286,62 @@ impl GameState {
             }
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // is_solvable only asks whether the liquids could be arranged into a solved board, not whether pours can get there,
+    // so a full board like "AB\nBA" still counts as solvable.
+    const KNOWN_SOLVABLE: &[&str] = &[
+        "AB\nBA",
+        "AB\nBA\n..",
+        "AAA\nBB\n..",
+        // Each color has exactly one size of container it fits
+        "ABA\nAB\n...",
+        "AAB\nBB",
+        "AAB\nAB\n.\n..",
+        "AAAA\nBBB.\nB\n...",
+        // One color has to be split over the two small containers
+        "ABAB\nBA\nBA\n...",
+        // A color bigger than any one container, spread over a big one and a tiny one
+        "AAAA\nA\n....",
+        "cap=4 AAAB\ncap=2 BBB\n..",
+    ];
+
+    const KNOWN_UNSOLVABLE: &[&str] = &[
+        "A.\n..",
+        "AB\nAB\nAB",
+        "AAB\nB..\n...",
+        "A..\nAAA\n...",
+        "AB.\nAB.\n..",
+        // Three of each color, but no way to make three out of 4, 2 and 2
+        "AABB\nBA\n..",
+        "AAAB\nBB\n..",
+        "ABBB\nAA\n....\n..",
+        "AAAAA\nBB..\n...",
+        "AAAAAB\nBB\n....",
+        "ABC\nCBA\n..\n.",
+    ];
+
+    #[test]
+    fn known_solvable_boards() {
+        for repr in KNOWN_SOLVABLE {
+            GameState::debug_assert_solvable(repr);
+        }
+    }
+
+    #[test]
+    fn solutions_wait_for_hidden_packets() {
+        let state = GameState::new_from_repr("?A?BA\n?B?AB\n.....\n.....");
+        assert_eq!(state.solve(), None);
+        assert_eq!(state.solve_iter().next(), None);
+    }
+
+    #[test]
+    fn known_unsolvable_boards() {
+        for repr in KNOWN_UNSOLVABLE {
+            assert!(!GameState::new_from_repr(repr).is_solvable(), "expected an unsolvable board:\n{}", repr);
+        }
+    }
+}